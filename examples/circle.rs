@@ -18,6 +18,7 @@ use std::time::Duration;
 
 use plugwise::Device;
 use plugwise::ProtocolSnoop;
+use plugwise::Reconnect;
 use plugwise::plugwise;
 
 const CONFIG: &'static str = ".plugwise.toml";
@@ -118,10 +119,25 @@ fn plugwise_actions(matches: &getopts::Matches, serial: Option<String>, mac: u64
         _ => ProtocolSnoop::All(&mut debug)
     };
     let device = match serial {
+        // a remote serial server is addressed by host and port
+        Some(ref serial) if serial.contains(':') => {
+            Device::Tcp{addr: serial.clone(),
+                        timeout: Duration::from_millis(1000),
+                        retries: 3,
+                        snoop: snoop,
+                        reconnect: Reconnect{
+                            attempts: 3,
+                            backoff: Duration::from_millis(500)
+                        }}
+        },
         Some(ref serial) => Device::SerialExt{port: serial.clone(),
                                               timeout: Duration::from_millis(1000),
                                               retries: 3,
-                                              snoop: snoop},
+                                              snoop: snoop,
+                                              reconnect: Reconnect{
+                                                  attempts: 3,
+                                                  backoff: Duration::from_millis(500)
+                                              }},
         None => Device::Simulator
     };
     if serial.is_none() {
@@ -143,10 +159,26 @@ fn plugwise_actions(matches: &getopts::Matches, serial: Option<String>, mac: u64
     } else if matches.opt_present("d") {
         circle.switch_off().ok().expect("unable to switch on circle");
         println!("circle {:016X} switched off", mac);
+    } else if matches.opt_present("i") {
+        let info = circle.get_info().ok().expect("unable to retrieve circle information");
+        println!("circle {:016X} product: {:?}, hardware: {}, firmware: {}", mac,
+                 info.version.product, info.version.hardware,
+                 time::at_utc(info.version.firmware).strftime("%Y-%m-%d").unwrap());
+        println!("circle {:016X} relay_status: {}, relay_locked: {}, frequency: {} Hz", mac,
+                 info.relay_state, info.relay_locked, info.hz);
+    } else if matches.opt_present("g") {
+        let ping = circle.ping().ok().expect("unable to ping circle");
+        let round_trip = ping.round_trip.as_secs() * 1000 +
+                         (ping.round_trip.subsec_nanos() / 1000000) as u64;
+        println!("circle {:016X} rssi in: {}, rssi out: {}, round-trip: {} ms", mac,
+                 ping.rssi_in, ping.rssi_out, round_trip);
     } else if matches.opt_present("p") {
-        let watts = circle.get_actual_watt_usage().ok()
-                                                  .expect("unable to retrieve actual power usage");
-        println!("circle {:016X} actual supplied power is: {} W", mac, watts);
+        let reading = circle.get_power_reading().ok()
+                                                .expect("unable to retrieve actual power usage");
+        println!("circle {:016X} actual supplied power is: {} W (last second: {} W)", mac,
+                 reading.watts_8s, reading.watts_1s);
+        println!("circle {:016X} power usage this hour is: {} kWh", mac,
+                 reading.hour.consumed - reading.hour.produced);
     } else if let Some(days) = matches.opt_str("o") {
         let days = u32::from_str_radix(&days, 10).ok()
             .expect("provided number of days must be a positive decimal number");
@@ -184,7 +216,7 @@ fn main() {
 
     let mut opts = Options::new();
 
-    opts.optopt("s", "serial", "configure serial-port", "DEVICE")
+    opts.optopt("s", "serial", "configure serial-port (or HOST:PORT of a serial server)", "DEVICE")
         .optflag("t", "stub", "configure to use stub implementation")
         .optopt("a", "alias", "assign a alias to Mac", "NAME")
         .optflag("u", "unalias", "forget alias")
@@ -192,6 +224,8 @@ fn main() {
         .optflag("r", "relaystatus", "print the relay status of a circle")
         .optflag("e", "enable", "enable the relay of a circle")
         .optflag("d", "disable", "disable the relay of a circle")
+        .optflag("i", "info", "print the version and status information of a circle")
+        .optflag("g", "ping", "print the link quality of a circle")
         .optflag("p", "powerusage", "print the actual power usage of a circle")
         .optopt("o", "powersince", "print the total power usage of a given number of days", "DAYS")
         .optflag("c", "clock", "print the internal clock value of a circle")
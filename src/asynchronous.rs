@@ -0,0 +1,523 @@
+//! Asynchronous front end of this crate (enabled by the `async` feature), based on the futures of
+//! the [futures](../futures/index.html) crate, which can be used by any executor (i.e. tokio).
+//!
+//! The requests are handled by a dedicated I/O thread (one per `AsyncPlugwise` instance) which
+//! uses the same protocol implementation as the blocking API, so the executor is never blocked by
+//! the communication with the Plugwise devices. Everything which needs the link to the USB stick
+//! (including `subscribe` and the commands which are queued for sleeping devices) is handled by
+//! the I/O thread; only the state which a sleeping device reported during its last `update` is
+//! returned immediately.
+//!
+//! The I/O thread takes the place of a non-blocking transport: the port of the `serial` crate can
+//! only be used blocking, the futures crate (0.1) does not include a reactor to wait for the port
+//! to be ready and `async` functions are not available in the edition of this crate (2015). A
+//! non-blocking link would therefore need a platform specific event loop for the USB stick, the
+//! serial server and custom transports alike, next to a second implementation of the protocol. The
+//! requests of a single `AsyncPlugwise` are handled one after another anyway (like the requests of
+//! different threads using the blocking API), so the thread only costs a context switch per
+//! request.
+//!
+//! ```
+//! extern crate futures;
+//! extern crate plugwise;
+//!
+//! use futures::Future;
+//! use plugwise::asynchronous::AsyncPlugwise;
+//!
+//! # fn main() {
+//! let circle = AsyncPlugwise::new(plugwise::Device::Simulator)
+//!     .and_then(|stub| stub.create_circle(0x01234567890ABCDEF))
+//!     .wait()
+//!     .unwrap();
+//! circle.switch_on().wait().unwrap();
+//! # }
+//! ```
+
+use std::io;
+use std::thread;
+use std::sync::{Arc, Mutex, mpsc};
+use std::collections::BTreeMap;
+use futures::{Future, Poll, Async};
+use futures::sync::oneshot;
+
+use super::{plugwise, acquire, error, time};
+use super::{Device, Plugwise, Circle, CirclePlus, SleepingDevice, Scan, Sense, Switch, Event,
+            StickInfo, NodeVersion, NodeInfo, LinkQuality, PowerReading, Energy, Schedule,
+            AwakeReason};
+
+// work to be done by the I/O thread
+trait Job: Send {
+    fn run(self: Box<Self>);
+}
+
+impl<F: FnOnce() + Send> Job for F {
+    fn run(self: Box<Self>) {
+        (*self)()
+    }
+}
+
+// I/O thread which executes the jobs one after another
+struct Worker {
+    sender: Mutex<mpsc::Sender<Box<Job>>>
+}
+
+impl Worker {
+    fn new() -> Worker {
+        let (sender, receiver) = mpsc::channel::<Box<Job>>();
+
+        thread::spawn(move || {
+            for job in receiver {
+                job.run();
+            }
+        });
+
+        Worker {
+            sender: Mutex::new(sender)
+        }
+    }
+
+    fn execute<T, F>(&self, f: F) -> Request<T>
+        where T: Send + 'static,
+              F: FnOnce() -> error::PlResult<T> + Send + 'static {
+        let (sender, receiver) = oneshot::channel();
+
+        // when the I/O thread is gone, the request is canceled (which is reported by the future)
+        let _ = acquire(&self.sender).send(Box::new(move || {
+            let _ = sender.send(f());
+        }));
+
+        Request {
+            receiver: receiver
+        }
+    }
+}
+
+/// Future of a request which is handled by the I/O thread.
+pub struct Request<T> {
+    receiver: oneshot::Receiver<error::PlResult<T>>
+}
+
+impl<T> Future for Request<T> {
+    type Item = T;
+    type Error = error::PlError;
+
+    fn poll(&mut self) -> Poll<T, error::PlError> {
+        match self.receiver.poll() {
+            Ok(Async::Ready(Ok(result))) => Ok(Async::Ready(result)),
+            Ok(Async::Ready(Err(e))) => Err(e),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(_) => Err(error::PlError::Io(io::Error::new(io::ErrorKind::BrokenPipe,
+                                                            "I/O thread has stopped")))
+        }
+    }
+}
+
+/// Asynchronous version of `Plugwise`.
+#[derive(Clone)]
+pub struct AsyncPlugwise {
+    plugwise: Arc<Box<Plugwise<'static>>>,
+    worker: Arc<Worker>
+}
+
+impl AsyncPlugwise {
+    /// Create instance to communicate against a (simulator) Plugwise USB stick (see `plugwise`).
+    pub fn new(device: Device<'static>) -> Request<AsyncPlugwise> {
+        let worker = Arc::new(Worker::new());
+        let result_worker = worker.clone();
+
+        worker.execute(move || {
+            let plugwise = try!(plugwise(device));
+            Ok(AsyncPlugwise {
+                plugwise: Arc::new(plugwise),
+                worker: result_worker
+            })
+        })
+    }
+
+    fn execute<T, F>(&self, f: F) -> Request<T>
+        where T: Send + 'static,
+              F: FnOnce(&Plugwise<'static>) -> error::PlResult<T> + Send + 'static {
+        let plugwise = self.plugwise.clone();
+        self.worker.execute(move || f(&**plugwise))
+    }
+
+    /// See `Plugwise::stick_info`.
+    pub fn stick_info(&self) -> Request<StickInfo> {
+        self.execute(|plugwise| plugwise.stick_info())
+    }
+
+    /// See `Plugwise::create_circle`.
+    pub fn create_circle(&self, mac: u64) -> Request<AsyncCircle> {
+        let worker = self.worker.clone();
+        self.execute(move |plugwise| {
+            let circle = try!(plugwise.create_circle(mac));
+            Ok(AsyncCircleDevice {
+                circle: Arc::new(circle),
+                worker: worker
+            })
+        })
+    }
+
+    /// See `Plugwise::create_circle_plus`.
+    pub fn create_circle_plus(&self) -> Request<AsyncCirclePlus> {
+        let worker = self.worker.clone();
+        self.execute(move |plugwise| {
+            let circle_plus = try!(plugwise.create_circle_plus());
+            Ok(AsyncCircleDevice {
+                circle: Arc::new(circle_plus),
+                worker: worker
+            })
+        })
+    }
+
+    /// See `Plugwise::get_linked_circles`.
+    pub fn get_linked_circles(&self) -> Request<Vec<u64>> {
+        self.execute(|plugwise| plugwise.get_linked_circles())
+    }
+
+    /// See `Plugwise::enable_join`.
+    pub fn enable_join(&self, enable: bool) -> Request<()> {
+        self.execute(move |plugwise| plugwise.enable_join(enable))
+    }
+
+    /// See `Plugwise::get_join_requests`.
+    pub fn get_join_requests(&self) -> Request<Vec<u64>> {
+        self.execute(|plugwise| plugwise.get_join_requests())
+    }
+
+    /// See `Plugwise::accept_join`.
+    pub fn accept_join(&self, mac: u64, accept: bool) -> Request<()> {
+        self.execute(move |plugwise| plugwise.accept_join(mac, accept))
+    }
+
+    /// See `Plugwise::remove_node`.
+    pub fn remove_node(&self, mac: u64) -> Request<()> {
+        self.execute(move |plugwise| plugwise.remove_node(mac))
+    }
+
+    /// See `Plugwise::switch_group`.
+    pub fn switch_group(&self,
+                        macs: Vec<u64>,
+                        on: bool) -> Request<BTreeMap<u64, error::PlResult<()>>> {
+        self.execute(move |plugwise| plugwise.switch_group(&macs, on))
+    }
+
+    /// See `Plugwise::switch_all`.
+    pub fn switch_all(&self, on: bool) -> Request<BTreeMap<u64, error::PlResult<()>>> {
+        self.execute(move |plugwise| plugwise.switch_all(on))
+    }
+
+    /// See `Plugwise::poll_power`.
+    pub fn poll_power(&self,
+                      macs: Vec<u64>) -> Request<BTreeMap<u64, error::PlResult<PowerReading>>> {
+        self.execute(move |plugwise| plugwise.poll_power(&macs))
+    }
+
+    /// See `Plugwise::subscribe` (the events are collected by the I/O thread, so the receiver
+    /// should not be used to wait for events by the executor).
+    pub fn subscribe(&self) -> Request<mpsc::Receiver<Event>> {
+        self.execute(|plugwise| Ok(plugwise.subscribe()))
+    }
+
+    /// See `Plugwise::poll_events`.
+    pub fn poll_events(&self) -> Request<()> {
+        self.execute(|plugwise| plugwise.poll_events())
+    }
+
+    /// See `Plugwise::create_scan`.
+    pub fn create_scan(&self, mac: u64) -> AsyncScan {
+        self.create_sleeping_device(self.plugwise.create_scan(mac))
+    }
+
+    /// See `Plugwise::create_sense`.
+    pub fn create_sense(&self, mac: u64) -> AsyncSense {
+        self.create_sleeping_device(self.plugwise.create_sense(mac))
+    }
+
+    /// See `Plugwise::create_switch`.
+    pub fn create_switch(&self, mac: u64) -> AsyncSwitch {
+        self.create_sleeping_device(self.plugwise.create_switch(mac))
+    }
+
+    fn create_sleeping_device<D>(&self, device: Box<D>) -> AsyncSleepingDevice<D>
+        where D: SleepingDevice + ?Sized + 'static {
+        AsyncSleepingDevice {
+            device: Arc::new(device),
+            worker: self.worker.clone()
+        }
+    }
+}
+
+/// Asynchronous version of a `Circle` (see `AsyncCircle` and `AsyncCirclePlus`).
+pub struct AsyncCircleDevice<C: ?Sized> {
+    circle: Arc<Box<C>>,
+    worker: Arc<Worker>
+}
+
+/// Asynchronous version of `Circle`.
+pub type AsyncCircle = AsyncCircleDevice<Circle + 'static>;
+/// Asynchronous version of `CirclePlus` (which also provides the requests of a Circle).
+pub type AsyncCirclePlus = AsyncCircleDevice<CirclePlus + 'static>;
+
+impl<C: ?Sized> Clone for AsyncCircleDevice<C> {
+    fn clone(&self) -> AsyncCircleDevice<C> {
+        AsyncCircleDevice {
+            circle: self.circle.clone(),
+            worker: self.worker.clone()
+        }
+    }
+}
+
+impl<C: Circle + ?Sized + 'static> AsyncCircleDevice<C> {
+    fn execute<T, F>(&self, f: F) -> Request<T>
+        where T: Send + 'static,
+              F: FnOnce(&C) -> error::PlResult<T> + Send + 'static {
+        let circle = self.circle.clone();
+        self.worker.execute(move || f(&**circle))
+    }
+
+    /// See `Circle::get_mac`.
+    pub fn get_mac(&self) -> u64 {
+        self.circle.get_mac()
+    }
+
+    /// See `Circle::get_version`.
+    pub fn get_version(&self) -> Request<NodeVersion> {
+        self.execute(|circle| circle.get_version())
+    }
+
+    /// See `Circle::get_info`.
+    pub fn get_info(&self) -> Request<NodeInfo> {
+        self.execute(|circle| circle.get_info())
+    }
+
+    /// See `Circle::ping`.
+    pub fn ping(&self) -> Request<LinkQuality> {
+        self.execute(|circle| circle.ping())
+    }
+
+    /// See `Circle::switch_on`.
+    pub fn switch_on(&self) -> Request<()> {
+        self.execute(|circle| circle.switch_on())
+    }
+
+    /// See `Circle::switch_off`.
+    pub fn switch_off(&self) -> Request<()> {
+        self.execute(|circle| circle.switch_off())
+    }
+
+    /// See `Circle::is_switched_on`.
+    pub fn is_switched_on(&self) -> Request<bool> {
+        self.execute(|circle| circle.is_switched_on())
+    }
+
+    /// See `Circle::set_relay_lock`.
+    pub fn set_relay_lock(&self, lock: bool) -> Request<()> {
+        self.execute(move |circle| circle.set_relay_lock(lock))
+    }
+
+    /// See `Circle::is_relay_locked`.
+    pub fn is_relay_locked(&self) -> Request<bool> {
+        self.execute(|circle| circle.is_relay_locked())
+    }
+
+    /// See `Circle::get_actual_watt_usage`.
+    pub fn get_actual_watt_usage(&self) -> Request<f64> {
+        self.execute(|circle| circle.get_actual_watt_usage())
+    }
+
+    /// See `Circle::get_power_reading`.
+    pub fn get_power_reading(&self) -> Request<PowerReading> {
+        self.execute(|circle| circle.get_power_reading())
+    }
+
+    /// See `Circle::get_clock`.
+    pub fn get_clock(&self) -> Request<time::Tm> {
+        self.execute(|circle| circle.get_clock())
+    }
+
+    /// See `Circle::set_clock`.
+    pub fn set_clock(&self, tm: time::Tm) -> Request<()> {
+        self.execute(move |circle| circle.set_clock(tm))
+    }
+
+    /// See `Circle::get_power_buffer`.
+    pub fn get_power_buffer(&self,
+                            max_entries: Option<u32>) -> Request<BTreeMap<time::Timespec, f64>> {
+        self.execute(move |circle| circle.get_power_buffer(max_entries))
+    }
+
+    /// See `Circle::get_energy_buffer`.
+    pub fn get_energy_buffer(&self, max_entries: Option<u32>)
+                             -> Request<BTreeMap<time::Timespec, Energy>> {
+        self.execute(move |circle| circle.get_energy_buffer(max_entries))
+    }
+
+    /// See `Circle::set_log_interval`.
+    pub fn set_log_interval(&self, consumption: u16, production: u16) -> Request<()> {
+        self.execute(move |circle| circle.set_log_interval(consumption, production))
+    }
+
+    /// See `Circle::set_schedule`.
+    pub fn set_schedule(&self, schedule: Schedule) -> Request<()> {
+        self.execute(move |circle| circle.set_schedule(&schedule))
+    }
+
+    /// See `Circle::enable_schedule`.
+    pub fn enable_schedule(&self, enable: bool) -> Request<()> {
+        self.execute(move |circle| circle.enable_schedule(enable))
+    }
+
+    /// See `Circle::get_schedule`.
+    pub fn get_schedule(&self) -> Request<Option<Schedule>> {
+        self.execute(|circle| Ok(circle.get_schedule()))
+    }
+}
+
+impl AsyncCirclePlus {
+    /// See `CirclePlus::get_realtime_clock`.
+    pub fn get_realtime_clock(&self) -> Request<time::Tm> {
+        self.execute(|circle_plus| circle_plus.get_realtime_clock())
+    }
+
+    /// See `CirclePlus::set_realtime_clock`.
+    pub fn set_realtime_clock(&self, tm: time::Tm) -> Request<()> {
+        self.execute(move |circle_plus| circle_plus.set_realtime_clock(tm))
+    }
+}
+
+/// Asynchronous version of a `SleepingDevice` (see `AsyncScan`, `AsyncSense` and `AsyncSwitch`).
+pub struct AsyncSleepingDevice<D: ?Sized> {
+    device: Arc<Box<D>>,
+    worker: Arc<Worker>
+}
+
+/// Asynchronous version of `Scan`.
+pub type AsyncScan = AsyncSleepingDevice<Scan + 'static>;
+/// Asynchronous version of `Sense`.
+pub type AsyncSense = AsyncSleepingDevice<Sense + 'static>;
+/// Asynchronous version of `Switch`.
+pub type AsyncSwitch = AsyncSleepingDevice<Switch + 'static>;
+
+impl<D: ?Sized> Clone for AsyncSleepingDevice<D> {
+    fn clone(&self) -> AsyncSleepingDevice<D> {
+        AsyncSleepingDevice {
+            device: self.device.clone(),
+            worker: self.worker.clone()
+        }
+    }
+}
+
+impl<D: SleepingDevice + ?Sized + 'static> AsyncSleepingDevice<D> {
+    fn execute<T, F>(&self, f: F) -> Request<T>
+        where T: Send + 'static,
+              F: FnOnce(&D) -> error::PlResult<T> + Send + 'static {
+        let device = self.device.clone();
+        self.worker.execute(move || f(&**device))
+    }
+
+    /// See `SleepingDevice::get_mac`.
+    pub fn get_mac(&self) -> u64 {
+        self.device.get_mac()
+    }
+
+    /// See `SleepingDevice::update`.
+    pub fn update(&self) -> Request<()> {
+        self.execute(|device| device.update())
+    }
+
+    /// See `SleepingDevice::get_last_awake`.
+    pub fn get_last_awake(&self) -> Option<(time::Timespec, AwakeReason)> {
+        self.device.get_last_awake()
+    }
+
+    /// See `SleepingDevice::get_pending_commands`.
+    pub fn get_pending_commands(&self) -> Request<usize> {
+        self.execute(|device| Ok(device.get_pending_commands()))
+    }
+
+    /// See `SleepingDevice::set_sleep_config`.
+    pub fn set_sleep_config(&self,
+                            stay_active: u8,
+                            sleep_for: u16,
+                            maintenance_interval: u16) -> Request<()> {
+        self.execute(move |device| {
+            device.set_sleep_config(stay_active, sleep_for, maintenance_interval);
+            Ok(())
+        })
+    }
+}
+
+impl AsyncScan {
+    /// See `Scan::is_motion_detected`.
+    pub fn is_motion_detected(&self) -> Option<bool> {
+        self.device.is_motion_detected()
+    }
+
+    /// See `Scan::set_motion_config`.
+    pub fn set_motion_config(&self,
+                             sensitivity: u8,
+                             reset_timer: u8,
+                             daylight: bool) -> Request<()> {
+        self.execute(move |scan| {
+            scan.set_motion_config(sensitivity, reset_timer, daylight);
+            Ok(())
+        })
+    }
+}
+
+impl AsyncSense {
+    /// See `Sense::get_temperature`.
+    pub fn get_temperature(&self) -> Option<f64> {
+        self.device.get_temperature()
+    }
+
+    /// See `Sense::get_humidity`.
+    pub fn get_humidity(&self) -> Option<f64> {
+        self.device.get_humidity()
+    }
+
+    /// See `Sense::set_report_interval`.
+    pub fn set_report_interval(&self, interval: u8) -> Request<()> {
+        self.execute(move |sense| {
+            sense.set_report_interval(interval);
+            Ok(())
+        })
+    }
+}
+
+impl AsyncSwitch {
+    /// See `Switch::get_button_state`.
+    pub fn get_button_state(&self, button: u8) -> Option<bool> {
+        self.device.get_button_state(button)
+    }
+}
+
+#[test]
+fn smoke_async_stub() {
+    let stub = AsyncPlugwise::new(Device::Simulator).wait().unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).wait().unwrap();
+    assert_eq!(circle.get_mac(), 0x0123456789ABCDEF);
+
+    // requests are handled in order of arrival
+    let switched = circle.switch_on().join(circle.is_switched_on()).wait().unwrap();
+    assert_eq!(switched, ((), true));
+    circle.get_power_reading().wait().unwrap();
+    assert_eq!(stub.get_linked_circles().wait().unwrap(), vec![0x0123456789ABCDEF]);
+    assert!(stub.switch_group(vec![0x0123456789ABCDEF], false).wait().unwrap()
+                .values().all(|res| res.is_ok()));
+    assert_eq!(circle.is_switched_on().wait().unwrap(), false);
+
+    let events = stub.subscribe().wait().unwrap();
+    let circle_plus = stub.create_circle_plus().wait().unwrap();
+    circle_plus.get_realtime_clock().wait().unwrap();
+    circle_plus.switch_on().wait().unwrap();
+
+    let scan = stub.create_scan(0x0123456789ABCDEF);
+    scan.set_motion_config(5, 10, false).wait().unwrap();
+    assert_eq!(scan.get_pending_commands().wait().unwrap(), 1);
+    assert_eq!(scan.is_motion_detected(), None);
+    stub.enable_join(true).wait().unwrap();
+    stub.poll_events().wait().unwrap();
+    assert!(events.try_recv().is_ok());
+}
@@ -4,6 +4,7 @@ use std::error;
 use std::fmt;
 use std::io;
 use serial;
+use protocol::AckStatus;
 
 pub type PlResult<T> = result::Result<T, PlError>;
 
@@ -22,6 +23,11 @@ pub enum PlError {
     UnexpectedResponse,
     /// Protocol (i.e. CRC or formatting) error
     Protocol,
+    /// Negative (or unexpected) acknowledge received from the USB stick or a Circle (with the
+    /// address of the Circle, if known)
+    Nack(AckStatus, Option<u64>),
+    /// Circle+ refused to remove the Circle (with the given address) from its network
+    NotRemoved(u64),
 }
 
 impl From<io::Error> for PlError {
@@ -45,6 +51,10 @@ impl fmt::Display for PlError {
             PlError::InvalidTimestamp => write!(f, "Circle did return a invalid timestamp"),
             PlError::UnexpectedResponse => write!(f, "Unexpected response"),
             PlError::Protocol => write!(f, "Protocol error"),
+            PlError::Nack(status, None) => write!(f, "Negative acknowledge ({:?})", status),
+            PlError::Nack(status, Some(mac)) =>
+                write!(f, "Negative acknowledge ({:?}) from {:016X}", status, mac),
+            PlError::NotRemoved(mac) => write!(f, "Circle {:016X} not removed", mac),
         }
     }
 }
@@ -58,6 +68,8 @@ impl error::Error for PlError {
             PlError::InvalidTimestamp => "Circle did return a invalid timestamp",
             PlError::UnexpectedResponse => "Unexpected response",
             PlError::Protocol => "Protocol error",
+            PlError::Nack(..) => "Negative acknowledge",
+            PlError::NotRemoved(..) => "Circle not removed",
         }
     }
 
@@ -65,7 +77,8 @@ impl error::Error for PlError {
         match *self {
             PlError::Io(ref err) => err.cause(),
             PlError::Serial(ref err) => err.cause(),
-            PlError::NotOnline | PlError::InvalidTimestamp | PlError::UnexpectedResponse | PlError::Protocol => None,
+            PlError::NotOnline | PlError::InvalidTimestamp | PlError::UnexpectedResponse |
+                PlError::Protocol | PlError::Nack(..) | PlError::NotRemoved(..) => None,
         }
     }
 }
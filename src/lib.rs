@@ -1,13 +1,30 @@
 //! This crate implements a part of the Plugwise Circle and Plugwise Circle+ protocol (tested against
-//! 2010 firmware). It still requires the official tooling to configure and to link the plugs. This
-//! library supports the following operations:
+//! 2010 firmware). It still requires the official tooling to configure the Circle+. This library
+//! supports the following operations:
 //!
 //! * switch a Circle on or off;
+//! * switch a group of Circles (or all Circles) on or off at once;
 //! * retrieve the relay status of a Circle;
-//! * actual power usage of a Circle (in Watts);
-//! * power usage over time (retrieved per hour in kWh);
+//! * lock the relay of a Circle;
+//! * upload a weekly switching schedule to a Circle;
+//! * retrieve the address, network and firmware of the USB stick;
+//! * retrieve the product type, hardware revision and firmware release of a Circle;
+//! * check whether a Circle is reachable (including link quality);
+//! * actual power usage of a Circle (in Watts, sampled over 1 and 8 seconds) and the power usage
+//!   of the current hour (in kWh);
+//! * actual power usage of a group of Circles at once;
+//! * power usage over time (retrieved per log interval in kWh);
+//! * produced power (i.e. by solar panels) next to consumed power;
+//! * set the log interval of a Circle;
 //! * set clock of a Circle;
-//! * get actual clock of a Circle.
+//! * get actual clock of a Circle;
+//! * set and get the real-time clock of the Circle+;
+//! * list the Circles linked to the Circle+;
+//! * link new Circles to the Circle+ (or unlink them);
+//! * subscribe to unsolicited events (join requests, awake announcements, button presses, relays
+//!   switched with the button of a Circle, etc.);
+//! * motion of a Scan, temperature and humidity of a Sense and buttons of a Switch (battery
+//!   powered devices which are asleep most of the time).
 //!
 //! This library is inspired on a
 //! [Python implemention](https://bitbucket.org/hadara/python-plugwise/wiki/Home) which was based
@@ -18,6 +35,18 @@
 //! [serial-rs](../serial/index.html) crate, it is expected this crate also works on Windows and
 //! Mac OS X.
 //!
+//! A USB stick which is plugged into another machine can be used through a remote serial server
+//! (i.e. ser2net in "raw" mode), see `Device::Tcp`. Any other transport (implementing `Read` and
+//! `Write`) can be used as well, see `Device::Custom`.
+//!
+//! The link to a USB stick which has disappeared (i.e. because it has been unplugged or the USB
+//! bus has been reset) or to a serial server which closed the connection can be restored
+//! automatically, see `Reconnect`.
+//!
+//! All handles (the `Plugwise` instance, Circles, etc.) can be shared between threads; requests of
+//! different threads are handled one after another. An asynchronous front end (based on futures)
+//! is available in the `asynchronous` module when the `async` feature is enabled.
+//!
 //! Enable the relay of a Circle:
 //!
 //! ```ignore
@@ -37,19 +66,24 @@ extern crate num;
 extern crate time;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "async")]
+extern crate futures;
 
 mod stub;
+mod tcp;
 mod protocol;
 pub mod error;
+#[cfg(feature = "async")]
+pub mod asynchronous;
 
 use std::io::prelude::*;
 use std::time::Duration;
 use serial::prelude::*;
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::collections::BTreeMap;
+use std::sync::mpsc;
 
-pub use protocol::ProtocolSnoop;
+pub use protocol::{ProtocolSnoop, AckStatus, AwakeReason, ProductType, Event, Reconnect};
 
 const SETTINGS: serial::PortSettings = serial::PortSettings {
     baud_rate:      serial::Baud115200,
@@ -59,108 +93,588 @@ const SETTINGS: serial::PortSettings = serial::PortSettings {
     flow_control:   serial::FlowNone,
 };
 
+// number of entries in the node table of the Circle+
+const MAX_LINKED_NODES: u8 = 64;
+// default interval of the power usage logging of a Circle (in minutes)
+const DEFAULT_LOG_INTERVAL_MINUTES: u16 = 60;
+// number of days of a switching schedule
+const SCHEDULE_DAYS: usize = 7;
+// length of a slot of a switching schedule (in minutes)
+const SCHEDULE_SLOT_MINUTES: u16 = 15;
+// number of slots per day of a switching schedule
+const SCHEDULE_SLOTS_PER_DAY: usize = 24 * 60 / SCHEDULE_SLOT_MINUTES as usize;
+
+// lock a mutex (a mutex of which another thread panicked while holding it is used anyway)
+fn acquire<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 struct PlugwiseInner<'a, I> {
-    protocol: Rc<RefCell<protocol::Protocol<'a, I>>>
+    protocol: Arc<Mutex<protocol::Protocol<'a, I>>>,
+    circle_plus: u64,
+    // information of the USB stick as reported when it has been initialized
+    stick: protocol::ResInitialize
 }
 
 struct CircleInner<'a, I> {
-    protocol: Rc<RefCell<protocol::Protocol<'a, I>>>,
+    protocol: Arc<Mutex<protocol::Protocol<'a, I>>>,
     mac: u64,
-    calibration_data: protocol::ResCalibration
+    log_interval: Mutex<(u16, u16)>,
+    schedule: Mutex<Option<Schedule>>
 }
 
-impl<'a, I: Read+Write+'a> PlugwiseInner<'a, I> {
+struct SleepingInner<'a, I> {
+    protocol: Arc<Mutex<protocol::Protocol<'a, I>>>,
+    mac: u64,
+    last_awake: Mutex<Option<(time::Timespec, AwakeReason)>>,
+    groups: Mutex<BTreeMap<u8, bool>>,
+    report: Mutex<Option<(f64, f64)>>
+}
+
+impl<'a, I: Read+Write+Send+'a> PlugwiseInner<'a, I> {
     fn initialize(port: I) -> error::PlResult<PlugwiseInner<'a, I>> {
-        let plugwise = PlugwiseInner {
-            protocol: Rc::new(RefCell::new(protocol::Protocol::new(port)))
-        };
+        let protocol = Arc::new(Mutex::new(protocol::Protocol::new(port)));
 
-        let result = try!(plugwise.protocol.borrow_mut().initialize());
+        let result = try!(acquire(&protocol).initialize());
 
         if !result.is_online {
             return Err(error::PlError::NotOnline);
         }
 
-        Ok(plugwise)
+        Ok(PlugwiseInner {
+            protocol: protocol,
+            // the network is identified by the address of its coordinator (the Circle+)
+            circle_plus: result.network_id,
+            stick: result
+        })
     }
 
     fn set_snoop(&self, snoop: ProtocolSnoop<'a>) {
-        self.protocol.borrow_mut().set_snoop(snoop);
+        acquire(&self.protocol).set_snoop(snoop);
     }
 
     fn set_retries(&self, retries: u8) {
-        self.protocol.borrow_mut().set_retries(retries);
+        acquire(&self.protocol).set_retries(retries);
+    }
+
+    fn set_reconnect<F>(&self, reconnect: Reconnect, reopen: F)
+        where F: FnMut() -> error::PlResult<I> + Send + 'a {
+        acquire(&self.protocol).set_reconnect(reconnect, reopen);
+    }
+}
+
+/// Quality of the link between the Plugwise USB stick and a Circle.
+#[derive(Debug, Copy, Clone)]
+pub struct LinkQuality {
+    /// Signal strength of the ping request as received by the Circle
+    pub rssi_in: u8,
+    /// Signal strength of the ping response as received by the USB stick
+    pub rssi_out: u8,
+    /// Round-trip time of the ping
+    pub round_trip: Duration
+}
+
+/// Information about the Plugwise USB stick and the network it serves.
+#[derive(Debug, Copy, Clone)]
+pub struct StickInfo {
+    /// Unique address of the USB stick
+    pub mac: u64,
+    /// Identification of the network (the extended PAN id, which equals the address of the
+    /// Circle+)
+    pub network_id: u64,
+    /// Short identification of the network (the PAN id)
+    pub short_id: u16,
+    /// Whether the network is online
+    pub is_online: bool,
+    /// Release date of the firmware of the USB stick
+    pub firmware: time::Timespec
+}
+
+/// Product and version information of a Plugwise device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeVersion {
+    /// Kind of product
+    pub product: ProductType,
+    /// Hardware revision (i.e. `F` of a Circle type F), `None` when the model is not known
+    pub revision: Option<char>,
+    /// Hardware version as printed on the device (i.e. `6539-0701-4023`)
+    pub hardware: String,
+    /// Release date of the firmware
+    pub firmware: time::Timespec
+}
+
+/// Status information of a Circle.
+#[derive(Debug, Clone)]
+pub struct NodeInfo {
+    /// Whether the relay is switched on
+    pub relay_state: bool,
+    /// Whether the relay is locked
+    pub relay_locked: bool,
+    /// Frequency of the mains in Hz (0 when not known)
+    pub hz: u8,
+    /// Position of the last entry in the power usage log
+    pub last_logaddr: u32,
+    /// Date and hour of the actual log interval according to the Circle (in UTC), `None` when
+    /// the clock of the Circle is not set
+    pub datetime: Option<time::Tm>,
+    /// Product and version information
+    pub version: NodeVersion
+}
+
+/// Actual power usage of a Circle. Power usage is negative when power is produced.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PowerReading {
+    /// Power usage in Watts sampled over the last second
+    pub watts_1s: f64,
+    /// Power usage in Watts sampled over the last 8 seconds
+    pub watts_8s: f64,
+    /// Energy consumed and produced during the current hour
+    pub hour: Energy
+}
+
+impl PowerReading {
+    fn new(power_usage: &protocol::ResPowerUse,
+           calibration_data: protocol::ResCalibration) -> PowerReading {
+        PowerReading {
+            watts_1s: power_usage.pulse_1s.to_watts(calibration_data),
+            watts_8s: power_usage.pulse_8s.to_watts(calibration_data),
+            hour: Energy {
+                consumed: power_usage.pulse_hour.to_kwh(calibration_data),
+                produced: -power_usage.pulse_hour_produced.to_kwh(calibration_data)
+            }
+        }
+    }
+}
+
+/// Energy consumed and produced (i.e. by solar panels) by the devices behind a Circle in kWh.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Energy {
+    /// Consumed energy in kWh
+    pub consumed: f64,
+    /// Produced energy in kWh
+    pub produced: f64
+}
+
+/// Weekly switching schedule of a Circle with slots of 15 minutes. Days are numbered from 0
+/// (Monday) until 6 (Sunday).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    slots: Vec<bool>
+}
+
+impl Schedule {
+    /// Create a schedule of which the relay is switched off all the time.
+    pub fn new() -> Schedule {
+        Schedule {
+            slots: vec![false; SCHEDULE_DAYS * SCHEDULE_SLOTS_PER_DAY]
+        }
+    }
+
+    /// Switch the relay on (or off) at a given day from `from` until `until` (both in minutes
+    /// since midnight, rounded down to a slot of 15 minutes). Panics when the day or time is out
+    /// of range.
+    pub fn set(&mut self, day: u8, from: u16, until: u16, on: bool) {
+        assert!((day as usize) < SCHEDULE_DAYS && from <= until && until <= 24 * 60);
+
+        let offset = day as usize * SCHEDULE_SLOTS_PER_DAY;
+        let from = offset + (from / SCHEDULE_SLOT_MINUTES) as usize;
+        let until = offset + (until / SCHEDULE_SLOT_MINUTES) as usize;
+
+        for slot in &mut self.slots[from..until] {
+            *slot = on;
+        }
+    }
+
+    /// Indicates whether the relay is switched on at a given day and time (in minutes since
+    /// midnight). Panics when the day or time is out of range.
+    pub fn is_on(&self, day: u8, minute: u16) -> bool {
+        assert!((day as usize) < SCHEDULE_DAYS && minute < 24 * 60);
+
+        let offset = day as usize * SCHEDULE_SLOTS_PER_DAY;
+        self.slots[offset + (minute / SCHEDULE_SLOT_MINUTES) as usize]
     }
 }
 
 /// A abstract representation of the Plugwise USB stick.
-pub trait Plugwise<'a> {
+pub trait Plugwise<'a>: Send + Sync {
+    /// Retrieve the address, network and firmware information of the USB stick (the network
+    /// information is the information which has been reported when the USB stick has been
+    /// initialized).
+    fn stick_info(&self) -> error::PlResult<StickInfo>;
     /// Register a Circle (a wall outlet switch) and returns a abstract representation of the
     /// Circle.
     fn create_circle(&self, mac: u64) -> error::PlResult<Box<Circle + 'a>>;
+    /// Register the Circle+ (the coordinator of the network) and returns a abstract
+    /// representation of the Circle+.
+    fn create_circle_plus(&self) -> error::PlResult<Box<CirclePlus + 'a>>;
+    /// Retrieve the addresses of all Circles which are linked to the Circle+ (the Circle+ itself
+    /// is not included). The entries of the node table are requested at once; an entry which
+    /// cannot be retrieved is skipped.
+    fn get_linked_circles(&self) -> error::PlResult<Vec<u64>>;
+    /// Allow (or disallow) unlinked Circles to request to join the network of the Circle+.
+    fn enable_join(&self, enable: bool) -> error::PlResult<()>;
+    /// Retrieve the addresses of the Circles which requested to join the network since the last
+    /// call. Join requests are only received when joining is enabled (see `enable_join`). The
+    /// pending messages are received first, so this blocks until nothing has been received for
+    /// the timeout of the port (like `poll_events`).
+    fn get_join_requests(&self) -> error::PlResult<Vec<u64>>;
+    /// Accept (or reject) the join request of a Circle. The Circle reports it has joined the
+    /// network by `Event::JoinAccepted`.
+    fn accept_join(&self, mac: u64, accept: bool) -> error::PlResult<()>;
+    /// Unlink a Circle from the network of the Circle+. Fails with `PlError::NotRemoved` when
+    /// the Circle+ refuses to remove the Circle (e.g. when it is not linked).
+    fn remove_node(&self, mac: u64) -> error::PlResult<()>;
+    /// Switch the relays of a group of Circles on (or off). The requests are sent at once, so an
+    /// unresponsive Circle does not delay the others. The result is reported per Circle.
+    fn switch_group(&self, macs: &[u64], on: bool) -> error::PlResult<BTreeMap<u64, error::PlResult<()>>>;
+    /// Switch the relays of all linked Circles (including the Circle+) on (or off). See
+    /// `switch_group`.
+    fn switch_all(&self, on: bool) -> error::PlResult<BTreeMap<u64, error::PlResult<()>>>;
+    /// Retrieve the actual power usage of a group of Circles. The requests are pipelined (several
+    /// requests are sent before the responses are received), so an unresponsive Circle does not
+    /// delay the others. The result is reported per Circle. Circles of which no calibration
+    /// information is known yet (see `create_circle`) are calibrated first.
+    fn poll_power(&self, macs: &[u64]) -> error::PlResult<BTreeMap<u64, error::PlResult<PowerReading>>>;
+    /// Subscribe to the unsolicited events of the network. Events are collected whenever messages
+    /// are received (so also while a request is waiting for its response); use `poll_events` to
+    /// receive the events while no requests are made.
+    fn subscribe(&self) -> mpsc::Receiver<Event>;
+    /// Receive messages until nothing is received anymore (i.e. the timeout of the serial port)
+    /// and deliver the events to the subscribers.
+    fn poll_events(&self) -> error::PlResult<()>;
+    /// Register a Scan (a motion sensor) and returns a abstract representation of the Scan.
+    fn create_scan(&self, mac: u64) -> Box<Scan + 'a>;
+    /// Register a Sense (a temperature and humidity sensor) and returns a abstract representation
+    /// of the Sense.
+    fn create_sense(&self, mac: u64) -> Box<Sense + 'a>;
+    /// Register a Switch (a wall switch with two buttons) and returns a abstract representation of
+    /// the Switch.
+    fn create_switch(&self, mac: u64) -> Box<Switch + 'a>;
 }
 
 /// A abstract representation of the Plugwise Circle/Circle+.
-pub trait Circle {
+pub trait Circle: Send + Sync {
     /// Get unique address of the Circle
     fn get_mac(&self) -> u64;
-    /// Switch the relay of Circle on.
+    /// Retrieve the product type, hardware revision and firmware release date of the Circle.
+    fn get_version(&self) -> error::PlResult<NodeVersion>;
+    /// Retrieve the relay status, mains frequency, log position, clock and version of the Circle
+    /// at once.
+    fn get_info(&self) -> error::PlResult<NodeInfo>;
+    /// Check whether the Circle is reachable and report the quality of the link.
+    fn ping(&self) -> error::PlResult<LinkQuality>;
+    /// Switch the relay of Circle on. Succeeds only when the Circle confirms the relay has been
+    /// switched on.
     fn switch_on(&self) -> error::PlResult<()>;
-    /// Switch the relay of Circle off.
+    /// Switch the relay of Circle off. Succeeds only when the Circle confirms the relay has been
+    /// switched off.
     fn switch_off(&self) -> error::PlResult<()>;
     /// Retrieve the relay status of the Circle.
     fn is_switched_on(&self) -> error::PlResult<bool>;
-    /// Get actual power usage of the Circle in Watts (sampled over the last 8 seconds).
+    /// Lock (or unlock) the relay of the Circle. A locked relay cannot be switched by hand or by
+    /// schedules anymore (switching using `switch_on` or `switch_off` is still possible).
+    fn set_relay_lock(&self, lock: bool) -> error::PlResult<()>;
+    /// Retrieve whether the relay of the Circle is locked.
+    fn is_relay_locked(&self) -> error::PlResult<bool>;
+    /// Get actual power usage of the Circle in Watts (sampled over the last 8 seconds). The power
+    /// usage is negative when power is produced.
     fn get_actual_watt_usage(&self) -> error::PlResult<f64>;
+    /// Get the actual power usage of the Circle sampled over 1 and 8 seconds, together with the
+    /// energy consumed and produced during the current hour.
+    fn get_power_reading(&self) -> error::PlResult<PowerReading>;
     /// Get the actual clock state of the Circle (in UTC).
     fn get_clock(&self) -> error::PlResult<time::Tm>;
     /// Set the clock state of the Circle.
     fn set_clock(&self, tm: time::Tm) -> error::PlResult<()>;
     /// Retrieve a map of power usages over time. To retrieve only the last logged items specify
     /// the number of elements to retrieve in `max_entries`. Each entry contains the power usage of
-    /// one hour.
+    /// one log interval (one hour, unless changed with `set_log_interval`). The power usage is the
+    /// net power usage (negative when more power is produced than consumed).
     fn get_power_buffer(&self, max_entries: Option<u32>) -> error::PlResult<BTreeMap<time::Timespec, f64>>;
+    /// Simular to `get_power_buffer`, but reports the consumed and produced energy separately.
+    fn get_energy_buffer(&self, max_entries: Option<u32>) -> error::PlResult<BTreeMap<time::Timespec, Energy>>;
+    /// Set the interval of the power usage logging of the Circle in minutes (separately for
+    /// consumed and produced power; specify 0 to disable logging). The Circle cannot report its
+    /// configured interval, therefore this setting must be repeated for each new instance of a
+    /// Circle with a log interval other than 60 minutes.
+    fn set_log_interval(&self, consumption: u16, production: u16) -> error::PlResult<()>;
+    /// Upload a weekly switching schedule to the Circle. The schedule is executed by the Circle
+    /// itself (after it has been enabled with `enable_schedule`).
+    fn set_schedule(&self, schedule: &Schedule) -> error::PlResult<()>;
+    /// Enable (or disable) the switching schedule of the Circle.
+    fn enable_schedule(&self, enable: bool) -> error::PlResult<()>;
+    /// Retrieve the switching schedule which has been uploaded using this instance. The Circle
+    /// itself cannot report its schedule, therefore `None` is returned when no schedule has been
+    /// uploaded yet.
+    fn get_schedule(&self) -> Option<Schedule>;
+}
+
+/// A abstract representation of the Plugwise Circle+, which is a Circle that also coordinates
+/// the network.
+pub trait CirclePlus: Circle {
+    /// Get the real-time clock of the Circle+ (in UTC), which is used to timestamp the power usage
+    /// logs of the whole network.
+    fn get_realtime_clock(&self) -> error::PlResult<time::Tm>;
+    /// Set the real-time clock of the Circle+.
+    fn set_realtime_clock(&self, tm: time::Tm) -> error::PlResult<()>;
+}
+
+/// A abstract representation of a battery powered Plugwise device (Scan, Sense or Switch). These
+/// devices are asleep most of the time and only accept commands shortly after they announced to
+/// be awake. Commands are therefore queued until the device is awake (the commands which fail are
+/// reported by `Event::CommandFailed`), and the state of the device is only updated by `update`.
+pub trait SleepingDevice: Send + Sync {
+    /// Get unique address of the device
+    fn get_mac(&self) -> u64;
+    /// Receive the messages of the device until nothing is received anymore (i.e. the timeout of
+    /// the serial port) and send the queued commands as soon as the device is awake.
+    fn update(&self) -> error::PlResult<()>;
+    /// Time and reason of the last awake announcement of the device (`None` when the device has
+    /// not been noticed yet).
+    fn get_last_awake(&self) -> Option<(time::Timespec, AwakeReason)>;
+    /// Number of queued commands which wait for the device to be awake.
+    fn get_pending_commands(&self) -> usize;
+    /// Configure how long the device stays awake after an announcement (in seconds), how long it
+    /// sleeps (in minutes) and the interval of its maintenance announcements (in minutes).
+    fn set_sleep_config(&self, stay_active: u8, sleep_for: u16, maintenance_interval: u16);
+}
+
+/// A abstract representation of the Plugwise Scan (motion sensor).
+pub trait Scan: SleepingDevice {
+    /// Whether motion has been detected (`None` when the Scan has not reported yet).
+    fn is_motion_detected(&self) -> Option<bool>;
+    /// Configure the sensitivity of the motion detection, the time in minutes after which the
+    /// motion state is reset and whether motion is also detected in daylight.
+    fn set_motion_config(&self, sensitivity: u8, reset_timer: u8, daylight: bool);
+}
+
+/// A abstract representation of the Plugwise Sense (temperature and humidity sensor).
+pub trait Sense: SleepingDevice {
+    /// Last reported temperature in degrees Celsius (`None` when the Sense has not reported yet).
+    fn get_temperature(&self) -> Option<f64>;
+    /// Last reported relative humidity in percent (`None` when the Sense has not reported yet).
+    fn get_humidity(&self) -> Option<f64>;
+    /// Set the interval of the reports of the Sense in minutes.
+    fn set_report_interval(&self, interval: u8);
+}
+
+/// A abstract representation of the Plugwise Switch (wall switch with two buttons).
+pub trait Switch: SleepingDevice {
+    /// Last reported state of a button (numbered 1 and 2), `None` when the button has not been
+    /// pressed yet.
+    fn get_button_state(&self, button: u8) -> Option<bool>;
 }
 
-impl<'a, I:Read+Write+'a> Plugwise<'a> for PlugwiseInner<'a, I> {
+impl<'a, I:Read+Write+Send+'a> Plugwise<'a> for PlugwiseInner<'a, I> {
+    fn stick_info(&self) -> error::PlResult<StickInfo> {
+        let info = try!(acquire(&self.protocol).get_info(self.stick.mac));
+
+        Ok(StickInfo {
+            mac: self.stick.mac,
+            network_id: self.stick.network_id,
+            short_id: self.stick.short_id,
+            is_online: self.stick.is_online,
+            firmware: info.fw_ver
+        })
+    }
+
     fn create_circle(&self, mac: u64) -> error::PlResult<Box<Circle+ 'a>> {
-        let calibration_data = try!(self.protocol.borrow_mut().calibrate(mac));
+        try!(acquire(&self.protocol).calibrate(mac));
         Ok(Box::new(CircleInner {
             protocol: self.protocol.clone(),
             mac: mac,
-            calibration_data: calibration_data
+            log_interval: Mutex::new((DEFAULT_LOG_INTERVAL_MINUTES, DEFAULT_LOG_INTERVAL_MINUTES)),
+            schedule: Mutex::new(None)
+        }))
+    }
+
+    fn create_circle_plus(&self) -> error::PlResult<Box<CirclePlus + 'a>> {
+        try!(acquire(&self.protocol).calibrate(self.circle_plus));
+        Ok(Box::new(CircleInner {
+            protocol: self.protocol.clone(),
+            mac: self.circle_plus,
+            log_interval: Mutex::new((DEFAULT_LOG_INTERVAL_MINUTES, DEFAULT_LOG_INTERVAL_MINUTES)),
+            schedule: Mutex::new(None)
         }))
     }
+
+    fn get_linked_circles(&self) -> error::PlResult<Vec<u64>> {
+        let indices = (0..MAX_LINKED_NODES).collect::<Vec<u8>>();
+        let nodes = try!(acquire(&self.protocol).get_linked_node_group(self.circle_plus,
+                                                                       &indices));
+        let mut result = vec![];
+
+        for (index, node) in nodes {
+            match node {
+                Ok(node) => result.extend(node.mac),
+                // a entry which cannot be retrieved does not prevent retrieving the others
+                Err(e) => info!("skipping entry {} of the node table: {}", index, e)
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn enable_join(&self, enable: bool) -> error::PlResult<()> {
+        acquire(&self.protocol).set_join_mode(enable)
+    }
+
+    fn get_join_requests(&self) -> error::PlResult<Vec<u64>> {
+        acquire(&self.protocol).get_join_requests()
+    }
+
+    fn accept_join(&self, mac: u64, accept: bool) -> error::PlResult<()> {
+        acquire(&self.protocol).accept_join(mac, accept)
+    }
+
+    fn remove_node(&self, mac: u64) -> error::PlResult<()> {
+        let result = try!(acquire(&self.protocol).remove_node(self.circle_plus, mac));
+
+        if result.mac != mac {
+            return Err(error::PlError::UnexpectedResponse);
+        }
+
+        if !result.removed {
+            return Err(error::PlError::NotRemoved(mac));
+        }
+
+        Ok(())
+    }
+
+    fn switch_group(&self,
+                    macs: &[u64],
+                    on: bool) -> error::PlResult<BTreeMap<u64, error::PlResult<()>>> {
+        acquire(&self.protocol).switch_group(macs, on)
+    }
+
+    fn switch_all(&self, on: bool) -> error::PlResult<BTreeMap<u64, error::PlResult<()>>> {
+        let mut macs = try!(self.get_linked_circles());
+        macs.push(self.circle_plus);
+        self.switch_group(&macs, on)
+    }
+
+    fn poll_power(&self,
+                  macs: &[u64]) -> error::PlResult<BTreeMap<u64, error::PlResult<PowerReading>>> {
+        let mut result = BTreeMap::new();
+        let mut protocol = acquire(&self.protocol);
+        let uncalibrated = macs.iter().cloned()
+                               .filter(|&mac| protocol.get_calibration(mac).is_none())
+                               .collect::<Vec<_>>();
+
+        if !uncalibrated.is_empty() {
+            for (mac, calibration_data) in try!(protocol.calibrate_group(&uncalibrated)) {
+                if let Err(e) = calibration_data {
+                    result.insert(mac, Err(e));
+                }
+            }
+        }
+
+        let macs = macs.iter().cloned().filter(|&mac| protocol.get_calibration(mac).is_some())
+                       .collect::<Vec<_>>();
+
+        for (mac, power_usage) in try!(protocol.get_power_usage_group(&macs)) {
+            // the calibration information might be retrieved again (when the link to the USB
+            // stick has been restored in between)
+            let calibration_data = protocol.get_calibration(mac);
+            result.insert(mac, power_usage.and_then(|power_usage| match calibration_data {
+                Some(calibration_data) => Ok(PowerReading::new(&power_usage, calibration_data)),
+                None => Err(error::PlError::UnexpectedResponse)
+            }));
+        }
+
+        Ok(result)
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<Event> {
+        acquire(&self.protocol).subscribe()
+    }
+
+    fn poll_events(&self) -> error::PlResult<()> {
+        acquire(&self.protocol).poll()
+    }
+
+    fn create_scan(&self, mac: u64) -> Box<Scan + 'a> {
+        Box::new(SleepingInner::new(self.protocol.clone(), mac))
+    }
+
+    fn create_sense(&self, mac: u64) -> Box<Sense + 'a> {
+        Box::new(SleepingInner::new(self.protocol.clone(), mac))
+    }
+
+    fn create_switch(&self, mac: u64) -> Box<Switch + 'a> {
+        Box::new(SleepingInner::new(self.protocol.clone(), mac))
+    }
 }
 
-impl<'a, I:Read+Write+'a> Circle for CircleInner<'a, I> {
+impl<'a, I:Read+Write+Send+'a> Circle for CircleInner<'a, I> {
     fn get_mac(&self) -> u64 {
         self.mac
     }
 
+    fn get_version(&self) -> error::PlResult<NodeVersion> {
+        let info = try!(acquire(&self.protocol).get_info(self.mac));
+        Ok(self.get_version_helper(&info))
+    }
+
+    fn get_info(&self) -> error::PlResult<NodeInfo> {
+        let info = try!(acquire(&self.protocol).get_info(self.mac));
+        Ok(NodeInfo {
+            relay_state: info.relay_state,
+            relay_locked: info.relay_locked,
+            hz: info.hz,
+            last_logaddr: info.last_logaddr,
+            datetime: info.datetime.to_tm(),
+            version: self.get_version_helper(&info)
+        })
+    }
+
+    fn ping(&self) -> error::PlResult<LinkQuality> {
+        let ping = try!(acquire(&self.protocol).ping(self.mac));
+        Ok(LinkQuality {
+            rssi_in: ping.rssi_in,
+            rssi_out: ping.rssi_out,
+            round_trip: Duration::from_millis(ping.round_trip as u64)
+        })
+    }
+
     fn switch_on(&self) -> error::PlResult<()> {
-        try!(self.protocol.borrow_mut().switch(self.mac, true));
+        try!(acquire(&self.protocol).switch(self.mac, true));
         Ok(())
     }
 
     fn switch_off(&self) -> error::PlResult<()> {
-        try!(self.protocol.borrow_mut().switch(self.mac, false));
+        try!(acquire(&self.protocol).switch(self.mac, false));
         Ok(())
     }
 
     fn is_switched_on(&self) -> error::PlResult<bool> {
-        let info = try!(self.protocol.borrow_mut().get_info(self.mac));
+        let info = try!(acquire(&self.protocol).get_info(self.mac));
         Ok(info.relay_state)
     }
 
+    fn set_relay_lock(&self, lock: bool) -> error::PlResult<()> {
+        try!(acquire(&self.protocol).set_relay_lock(self.mac, lock));
+        Ok(())
+    }
+
+    fn is_relay_locked(&self) -> error::PlResult<bool> {
+        let info = try!(acquire(&self.protocol).get_info(self.mac));
+        Ok(info.relay_locked)
+    }
+
     fn get_actual_watt_usage(&self) -> error::PlResult<f64> {
-        let power_usage = try!(self.protocol.borrow_mut().get_power_usage(self.mac));
-        Ok(power_usage.pulse_8s.to_watts(self.calibration_data))
+        let power_usage = try!(acquire(&self.protocol).get_power_usage(self.mac));
+        Ok(power_usage.pulse_8s.to_watts(try!(self.get_calibration_data())))
+    }
+
+    fn get_power_reading(&self) -> error::PlResult<PowerReading> {
+        let power_usage = try!(acquire(&self.protocol).get_power_usage(self.mac));
+        Ok(PowerReading::new(&power_usage, try!(self.get_calibration_data())))
     }
 
     fn get_clock(&self) -> error::PlResult<time::Tm> {
-        let info = try!(self.protocol.borrow_mut().get_info(self.mac));
-        let clock = try!(self.protocol.borrow_mut().get_clock_info(self.mac));
+        let info = try!(acquire(&self.protocol).get_info(self.mac));
+        let clock = try!(acquire(&self.protocol).get_clock_info(self.mac));
 
         let mut tm = match info.datetime.to_tm() {
             Some(tm) => tm,
@@ -175,7 +689,7 @@ impl<'a, I:Read+Write+'a> Circle for CircleInner<'a, I> {
 
     fn set_clock(&self, tm: time::Tm) -> error::PlResult<()> {
         let clock_set = protocol::ReqClockSet::new_from_tm(tm);
-        try!(self.protocol.borrow_mut().set_clock(self.mac, clock_set));
+        try!(acquire(&self.protocol).set_clock(self.mac, clock_set));
         Ok(())
     }
 
@@ -183,8 +697,15 @@ impl<'a, I:Read+Write+'a> Circle for CircleInner<'a, I> {
     fn get_power_buffer(&self,
                         max_entries: Option<u32>)
                         -> error::PlResult<BTreeMap<time::Timespec, f64>> {
-        let mut result = BTreeMap::<time::Timespec, f64>::new();
-        let info = try!(self.protocol.borrow_mut().get_info(self.mac));
+        let buffer = try!(self.get_energy_buffer(max_entries));
+        Ok(buffer.into_iter().map(|(k, v)| (k, v.consumed - v.produced)).collect())
+    }
+
+    fn get_energy_buffer(&self,
+                         max_entries: Option<u32>)
+                         -> error::PlResult<BTreeMap<time::Timespec, Energy>> {
+        let mut result = BTreeMap::<time::Timespec, Energy>::new();
+        let info = try!(acquire(&self.protocol).get_info(self.mac));
         let start = match max_entries {
             None => 0,
             Some(n) => {
@@ -197,30 +718,228 @@ impl<'a, I:Read+Write+'a> Circle for CircleInner<'a, I> {
             }
         };
 
+        let (consumption, production) = *acquire(&self.log_interval);
+        let calibration_data = try!(self.get_calibration_data());
         for index in start..(info.last_logaddr + 1) {
-            let buffer = try!(self.protocol.borrow_mut().get_power_buffer(self.mac, index));
+            let buffer = try!(acquire(&self.protocol).get_power_buffer(self.mac,
+                                                                          index,
+                                                                          consumption as u32 * 60,
+                                                                          production as u32 * 60));
 
-            self.get_power_buffer_helper(&mut result, &buffer.datetime1, &buffer.pulses1);
-            self.get_power_buffer_helper(&mut result, &buffer.datetime2, &buffer.pulses2);
-            self.get_power_buffer_helper(&mut result, &buffer.datetime3, &buffer.pulses3);
-            self.get_power_buffer_helper(&mut result, &buffer.datetime4, &buffer.pulses4);
+            self.get_power_buffer_helper(&mut result, calibration_data,
+                                         &buffer.datetime1, &buffer.pulses1);
+            self.get_power_buffer_helper(&mut result, calibration_data,
+                                         &buffer.datetime2, &buffer.pulses2);
+            self.get_power_buffer_helper(&mut result, calibration_data,
+                                         &buffer.datetime3, &buffer.pulses3);
+            self.get_power_buffer_helper(&mut result, calibration_data,
+                                         &buffer.datetime4, &buffer.pulses4);
         }
 
         Ok(result)
     }
+
+    fn set_log_interval(&self, consumption: u16, production: u16) -> error::PlResult<()> {
+        try!(acquire(&self.protocol).set_log_interval(self.mac, consumption, production));
+        let mut log_interval = acquire(&self.log_interval);
+        let (old_consumption, old_production) = *log_interval;
+        *log_interval = (if consumption != 0 { consumption } else { old_consumption },
+                         if production != 0 { production } else { old_production });
+        Ok(())
+    }
+
+    fn set_schedule(&self, schedule: &Schedule) -> error::PlResult<()> {
+        // each request uploads 4 slots of the schedule
+        for (index, slots) in schedule.slots.chunks(4).enumerate() {
+            let slots = [slots[0], slots[1], slots[2], slots[3]];
+            try!(acquire(&self.protocol).set_schedule(self.mac, index as u32, slots));
+        }
+
+        *acquire(&self.schedule) = Some(schedule.clone());
+        Ok(())
+    }
+
+    fn enable_schedule(&self, enable: bool) -> error::PlResult<()> {
+        acquire(&self.protocol).enable_schedule(self.mac, enable)
+    }
+
+    fn get_schedule(&self) -> Option<Schedule> {
+        acquire(&self.schedule).clone()
+    }
+}
+
+impl<'a, I:Read+Write+Send+'a> CirclePlus for CircleInner<'a, I> {
+    fn get_realtime_clock(&self) -> error::PlResult<time::Tm> {
+        let clock = try!(acquire(&self.protocol).get_realtime_clock(self.mac));
+
+        match clock.to_tm() {
+            Some(tm) => Ok(tm),
+            None => Err(error::PlError::InvalidTimestamp)
+        }
+    }
+
+    fn set_realtime_clock(&self, tm: time::Tm) -> error::PlResult<()> {
+        let clock = protocol::RealTimeClock::new_from_tm(tm);
+        try!(acquire(&self.protocol).set_realtime_clock(self.mac, clock));
+        Ok(())
+    }
 }
 
-impl <'a, I:Read+Write+'a>  CircleInner<'a, I> {
+impl <'a, I:Read+Write+Send+'a>  CircleInner<'a, I> {
+    // calibration information of the Circle (which is retrieved again when the link to the USB
+    // stick has been restored)
+    fn get_calibration_data(&self) -> error::PlResult<protocol::ResCalibration> {
+        let mut protocol = acquire(&self.protocol);
+        match protocol.get_calibration(self.mac) {
+            Some(calibration_data) => Ok(calibration_data),
+            None => protocol.calibrate(self.mac)
+        }
+    }
+
+    fn get_version_helper(&self, info: &protocol::ResInfo) -> NodeVersion {
+        let model = info.get_model();
+        let product = match (info.node_type, model) {
+            // not all firmware reports the kind of product
+            (ProductType::Unknown(_), Some((product, _))) => product,
+            (product, _) => product
+        };
+
+        // the hardware version is shown as printed on the label of the Circle (when it is
+        // formatted as expected)
+        let hw_ver = &info.hw_ver;
+        let hardware = if hw_ver.len() == 12 && hw_ver.is_ascii() {
+            format!("{}-{}-{}", &hw_ver[0..4], &hw_ver[4..8], &hw_ver[8..12])
+        } else {
+            hw_ver.clone()
+        };
+
+        NodeVersion {
+            product: product,
+            revision: model.and_then(|(_, revision)| revision),
+            hardware: hardware,
+            firmware: info.fw_ver
+        }
+    }
+
     fn get_power_buffer_helper(&self,
-                               map: &mut BTreeMap<time::Timespec, f64>,
+                               map: &mut BTreeMap<time::Timespec, Energy>,
+                               calibration_data: protocol::ResCalibration,
                                datetime: &protocol::DateTime,
                                pulses: &protocol::Pulses) {
         if let Some(tm) = datetime.to_tm() {
-            let _ = map.insert(tm.to_timespec(), pulses.to_kwh(self.calibration_data));
+            // consumed and produced energy are logged as separate entries (with the same
+            // timestamp) when logging of produced energy is enabled
+            let kwh = pulses.to_kwh(calibration_data);
+            let entry = map.entry(tm.to_timespec()).or_insert(Energy {
+                consumed: 0.0,
+                produced: 0.0
+            });
+            if kwh < 0.0 {
+                entry.produced -= kwh;
+            } else {
+                entry.consumed += kwh;
+            }
+        }
+    }
+}
+
+impl<'a, I:Read+Write+Send+'a> SleepingInner<'a, I> {
+    fn new(protocol: Arc<Mutex<protocol::Protocol<'a, I>>>, mac: u64) -> SleepingInner<'a, I> {
+        SleepingInner {
+            protocol: protocol,
+            mac: mac,
+            last_awake: Mutex::new(None),
+            groups: Mutex::new(BTreeMap::new()),
+            report: Mutex::new(None)
+        }
+    }
+}
+
+impl<'a, I:Read+Write+Send+'a> SleepingDevice for SleepingInner<'a, I> {
+    fn get_mac(&self) -> u64 {
+        self.mac
+    }
+
+    fn update(&self) -> error::PlResult<()> {
+        let mut protocol = acquire(&self.protocol);
+        let result = protocol.poll();
+
+        // also process the messages which have been received before a failure
+        for msg in protocol.take_unsolicited_from(self.mac) {
+            match msg {
+                protocol::Message::ResAwake(_, res) => {
+                    *acquire(&self.last_awake) = Some((time::get_time(), res.reason));
+                },
+                protocol::Message::ResSwitchGroup(_, res) => {
+                    acquire(&self.groups).insert(res.group, res.on);
+                },
+                protocol::Message::ResSenseReport(_, res) => {
+                    *acquire(&self.report) = Some((res.temperature, res.humidity));
+                },
+                _ => {}
+            }
         }
+
+        result
+    }
+
+    fn get_last_awake(&self) -> Option<(time::Timespec, AwakeReason)> {
+        *acquire(&self.last_awake)
+    }
+
+    fn get_pending_commands(&self) -> usize {
+        acquire(&self.protocol).pending_commands(self.mac)
+    }
+
+    fn set_sleep_config(&self, stay_active: u8, sleep_for: u16, maintenance_interval: u16) {
+        acquire(&self.protocol).queue_sleep_config(self.mac,
+                                                      stay_active,
+                                                      sleep_for,
+                                                      maintenance_interval);
     }
 }
 
+impl<'a, I:Read+Write+Send+'a> Scan for SleepingInner<'a, I> {
+    fn is_motion_detected(&self) -> Option<bool> {
+        let groups = acquire(&self.groups);
+
+        if groups.is_empty() {
+            None
+        } else {
+            Some(groups.values().any(|&on| on))
+        }
+    }
+
+    fn set_motion_config(&self, sensitivity: u8, reset_timer: u8, daylight: bool) {
+        acquire(&self.protocol).queue_scan_config(self.mac, sensitivity, reset_timer, daylight);
+    }
+}
+
+impl<'a, I:Read+Write+Send+'a> Sense for SleepingInner<'a, I> {
+    fn get_temperature(&self) -> Option<f64> {
+        acquire(&self.report).map(|(temperature, _)| temperature)
+    }
+
+    fn get_humidity(&self) -> Option<f64> {
+        acquire(&self.report).map(|(_, humidity)| humidity)
+    }
+
+    fn set_report_interval(&self, interval: u8) {
+        acquire(&self.protocol).queue_sense_interval(self.mac, interval);
+    }
+}
+
+impl<'a, I:Read+Write+Send+'a> Switch for SleepingInner<'a, I> {
+    fn get_button_state(&self, button: u8) -> Option<bool> {
+        acquire(&self.groups).get(&button).cloned()
+    }
+}
+
+/// Transport to a Plugwise USB stick (implemented for every `Read + Write` implementation).
+pub trait ReadWrite: Read + Write {}
+
+impl<T: Read + Write> ReadWrite for T {}
+
 /// Specify which kind of Plugwise device to use
 pub enum Device<'a> {
     /// Create a link to the Plugwise USB stick to communicate with the Circle/Circle+ wall
@@ -237,12 +956,50 @@ pub enum Device<'a> {
         retries: u8,
         /// Tracing settings (including a reference to a `io::Write` instance to log the
         /// communication)
+        snoop: ProtocolSnoop<'a>,
+        /// Policy to restore the link when the USB stick has disappeared (i.e. it has been
+        /// unplugged)
+        reconnect: Reconnect
+    },
+    /// Create a link to a Plugwise USB stick which is exposed by a remote serial server (i.e.
+    /// ser2net in "raw" mode).
+    Tcp {
+        /// Address of the serial server (i.e. `raspberrypi:2000`)
+        addr: String,
+        /// Timeout in milliseconds;
+        timeout: Duration,
+        /// Number of attempts to retry communication;
+        retries: u8,
+        /// Tracing settings (including a reference to a `io::Write` instance to log the
+        /// communication)
+        snoop: ProtocolSnoop<'a>,
+        /// Policy to restore the link when the connection to the serial server has been lost
+        reconnect: Reconnect
+    },
+    /// Create a link to a Plugwise USB stick using a user-supplied transport (i.e. a RS-485
+    /// bridge or a pty). When no data is received in time, a read must fail with
+    /// `io::ErrorKind::TimedOut` (like a serial port of which the read timeout expired).
+    Custom {
+        /// Transport to the USB stick
+        port: Box<ReadWrite + Send + 'a>,
+        /// Number of attempts to retry communication;
+        retries: u8,
+        /// Tracing settings (including a reference to a `io::Write` instance to log the
+        /// communication)
         snoop: ProtocolSnoop<'a>
     },
     /// Create a simulation instance for development, testing and integration purposes
     Simulator,
 }
 
+// open and configure the serial port of a Plugwise USB stick
+fn open_serial(port: &str, timeout: Duration) -> error::PlResult<serial::SystemPort> {
+    let mut port = try!(serial::open(port));
+    try!(port.configure(&SETTINGS));
+    try!(port.set_timeout(timeout));
+    Ok(port)
+}
+
 /// Create instance to communicate against a (simulator) Plugwise USB stick and the associated
 /// Circle/Circle+ devices.
 ///
@@ -283,16 +1040,36 @@ pub fn plugwise<'a>(device: Device<'a>) -> error::PlResult<Box<Plugwise<'a>+ 'a>
                 port: port,
                 timeout: Duration::from_millis(2000),
                 retries: 5,
-                snoop: ProtocolSnoop::Nothing
+                snoop: ProtocolSnoop::Nothing,
+                reconnect: Reconnect {
+                    attempts: 0,
+                    backoff: Duration::from_millis(0)
+                }
             })
         },
-        Device::SerialExt{port, timeout, retries, snoop} => {
-            let mut port = try!(serial::open(&port[..]));
-            try!(port.configure(&SETTINGS));
-            try!(port.set_timeout(timeout));
+        Device::SerialExt{port, timeout, retries, snoop, reconnect} => {
+            let plugwise = try!(PlugwiseInner::initialize(try!(open_serial(&port, timeout))));
+            plugwise.set_snoop(snoop);
+            plugwise.set_retries(retries);
+            plugwise.set_reconnect(reconnect, move || open_serial(&port, timeout));
+
+            Ok(Box::new(plugwise))
+        },
+        Device::Custom{port, retries, snoop} => {
+            let plugwise = try!(PlugwiseInner::initialize(port));
+            plugwise.set_snoop(snoop);
+            plugwise.set_retries(retries);
+
+            Ok(Box::new(plugwise))
+        },
+        Device::Tcp{addr, timeout, retries, snoop, reconnect} => {
+            let port = try!(tcp::TcpPort::connect(addr.clone(), timeout));
             let plugwise = try!(PlugwiseInner::initialize(port));
             plugwise.set_snoop(snoop);
             plugwise.set_retries(retries);
+            plugwise.set_reconnect(reconnect, move || {
+                Ok(try!(tcp::TcpPort::connect(addr.clone(), timeout)))
+            });
 
             Ok(Box::new(plugwise))
         },
@@ -313,3 +1090,262 @@ fn smoke_external_stub() {
     circle.set_clock(tm).unwrap();
     circle.get_power_buffer(None).unwrap();
 }
+
+#[test]
+fn smoke_linked_circles() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let _ = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    assert_eq!(stub.get_linked_circles().unwrap(), vec![0x0123456789ABCDEF]);
+}
+
+#[test]
+fn smoke_ping() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    circle.ping().unwrap();
+}
+
+#[test]
+fn smoke_join() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let _ = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    let events = stub.subscribe();
+    stub.enable_join(true).unwrap();
+    stub.poll_events().unwrap();
+    match events.try_recv() {
+        Ok(Event::JoinRequest(_)) => {},
+        other => panic!("unexpected event: {:?}", other)
+    }
+    for mac in stub.get_join_requests().unwrap() {
+        stub.accept_join(mac, true).unwrap();
+        stub.poll_events().unwrap();
+        match events.try_recv() {
+            Ok(Event::JoinAccepted(accepted_mac)) => assert_eq!(mac, accepted_mac),
+            other => panic!("unexpected event: {:?}", other)
+        }
+    }
+    stub.enable_join(false).unwrap();
+    assert_eq!(stub.get_linked_circles().unwrap().len(), 2);
+}
+
+#[test]
+fn smoke_remove_node() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let _ = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    stub.remove_node(0x0123456789ABCDEF).unwrap();
+    match stub.remove_node(0x0123456789ABCDEF) {
+        Err(error::PlError::NotRemoved(0x0123456789ABCDEF)) => {},
+        _ => panic!("removing an unlinked Circle should fail")
+    }
+    assert_eq!(stub.get_linked_circles().unwrap().len(), 0);
+}
+
+#[test]
+fn smoke_realtime_clock() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let circle_plus = stub.create_circle_plus().unwrap();
+    let tm = circle_plus.get_realtime_clock().unwrap();
+    circle_plus.set_realtime_clock(tm).unwrap();
+}
+
+#[test]
+fn smoke_log_interval() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    circle.set_log_interval(15, 0).unwrap();
+    circle.get_power_buffer(Some(4)).unwrap();
+    circle.get_energy_buffer(Some(4)).unwrap();
+}
+
+#[test]
+fn smoke_relay_lock() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    circle.set_relay_lock(true).unwrap();
+    assert_eq!(circle.is_relay_locked().unwrap(), true);
+    circle.set_relay_lock(false).unwrap();
+    assert_eq!(circle.is_relay_locked().unwrap(), false);
+}
+
+#[test]
+fn smoke_schedule() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    assert_eq!(circle.get_schedule(), None);
+    let mut schedule = Schedule::new();
+    schedule.set(0, 8 * 60, 17 * 60 + 30, true);
+    assert!(schedule.is_on(0, 17 * 60 + 29));
+    assert!(!schedule.is_on(0, 17 * 60 + 30));
+    assert!(!schedule.is_on(1, 8 * 60));
+    circle.set_schedule(&schedule).unwrap();
+    circle.enable_schedule(true).unwrap();
+    assert_eq!(circle.get_schedule(), Some(schedule));
+}
+
+#[test]
+fn smoke_sleeping_devices() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let sense = stub.create_sense(0x000D6F0000ABCDEF);
+    sense.set_report_interval(5);
+    sense.set_sleep_config(10, 60, 1440);
+    assert_eq!(sense.get_pending_commands(), 2);
+    sense.update().unwrap();
+    assert_eq!(sense.get_last_awake(), None);
+    assert_eq!(sense.get_temperature(), None);
+    let scan = stub.create_scan(0x000D6F0000ABCDEE);
+    scan.update().unwrap();
+    assert_eq!(scan.is_motion_detected(), None);
+    let switch = stub.create_switch(0x000D6F0000ABCDED);
+    switch.update().unwrap();
+    assert_eq!(switch.get_button_state(1), None);
+}
+
+#[test]
+fn smoke_stick_info() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let stick = stub.stick_info().unwrap();
+    assert!(stick.is_online);
+    assert!(stick.mac != stick.network_id);
+}
+
+#[test]
+fn smoke_version() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    let version = circle.get_version().unwrap();
+    assert_eq!(version.product, ProductType::Circle);
+    assert_eq!(version.revision, Some('F'));
+    assert_eq!(version.hardware, "6539-0701-4023");
+}
+
+#[test]
+fn smoke_info() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    let info = circle.get_info().unwrap();
+    assert_eq!(info.hz, 50);
+    assert_eq!(info.version, circle.get_version().unwrap());
+}
+
+#[test]
+fn smoke_power_reading() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    let reading = circle.get_power_reading().unwrap();
+    assert!(reading.hour.consumed >= 0.0 && reading.hour.produced >= 0.0);
+}
+
+#[test]
+fn smoke_switch_all() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    let result = stub.switch_all(true).unwrap();
+    assert_eq!(result.len(), 2);
+    assert!(result.values().all(|res| res.is_ok()));
+    assert_eq!(circle.is_switched_on().unwrap(), true);
+}
+
+#[test]
+fn smoke_poll_power() {
+    let stub = plugwise(Device::Simulator).unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    let reading = circle.get_power_reading().unwrap();
+    let readings = stub.poll_power(&[0x0123456789ABCDEF, 0x000D6F0000C0FFEE]).unwrap();
+    assert_eq!(readings.len(), 2);
+    assert_eq!(readings[&0x0123456789ABCDEF].as_ref().ok(), Some(&reading));
+}
+
+#[test]
+fn smoke_threads() {
+    use std::thread;
+
+    let stub = Arc::new(plugwise(Device::Simulator).unwrap());
+    let circle = Arc::new(stub.create_circle(0x0123456789ABCDEF).unwrap());
+
+    let threads = (0..4).map(|n| {
+        let stub = stub.clone();
+        let circle = circle.clone();
+        thread::spawn(move || {
+            for _ in 0..10 {
+                if n % 2 == 0 {
+                    circle.switch_on().unwrap();
+                } else {
+                    circle.get_actual_watt_usage().unwrap();
+                }
+                stub.get_join_requests().unwrap();
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(circle.is_switched_on().unwrap(), true);
+}
+
+#[test]
+fn smoke_custom_port() {
+    let mut debug = vec![];
+    {
+        let stub = plugwise(Device::Custom {
+            port: Box::new(stub::Stub::new()),
+            retries: 1,
+            snoop: ProtocolSnoop::Debug(&mut debug)
+        }).unwrap();
+        let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+        circle.switch_on().unwrap();
+        assert_eq!(circle.is_switched_on().unwrap(), true);
+    }
+    assert!(String::from_utf8(debug).unwrap().contains("ReqSwitch"));
+}
+
+#[test]
+fn smoke_tcp_stub() {
+    use std::thread;
+    use std::net::TcpListener;
+
+    // serial server which relays to the simulator, the first connection is dropped after a few
+    // requests
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut port = stub::Stub::new();
+        for (n, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.unwrap();
+            let mut requests = 0;
+            stream.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+            stream.set_nodelay(true).unwrap();
+            while n > 0 || requests < 4 {
+                let mut buf = [0; 256];
+                match stream.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(size) => {
+                        requests += buf[..size].iter().filter(|&&b| b == b'\r').count();
+                        port.write_all(&buf[..size]).unwrap();
+                    },
+                    Err(_) => {}
+                }
+                while let Ok(size) = port.read(&mut buf) {
+                    stream.write_all(&buf[..size]).unwrap();
+                }
+            }
+        }
+    });
+
+    let stub = plugwise(Device::Tcp {
+        addr: addr.to_string(),
+        timeout: Duration::from_millis(200),
+        retries: 3,
+        snoop: ProtocolSnoop::Nothing,
+        reconnect: Reconnect {
+            attempts: 3,
+            backoff: Duration::from_millis(0)
+        }
+    }).unwrap();
+    let circle = stub.create_circle(0x0123456789ABCDEF).unwrap();
+    for _ in 0..4 {
+        circle.switch_on().unwrap();
+        assert_eq!(circle.is_switched_on().unwrap(), true);
+    }
+    assert_eq!(stub.get_linked_circles().unwrap(), vec![0x0123456789ABCDEF]);
+}
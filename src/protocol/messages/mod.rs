@@ -6,6 +6,19 @@ use super::super::error;
 const ADDR_OFFS: u32 = 278528;
 const BYTES_PER_POS: u32 = 32;
 const PULSES_PER_KW: f64 = 468.9385193;
+const DEFAULT_LOG_INTERVAL_SECS: u32 = 3600;
+const RELAY_ON: u8 = 0x01;
+const RELAY_LOCKED: u8 = 0x02;
+// model codes (part of the hardware version) of known products and their hardware revision
+const MODELS: [(&'static str, ProductType, Option<char>); 7] = [
+    ("070085", ProductType::Stick, None),
+    ("070073", ProductType::Circle, Some('B')),
+    ("070140", ProductType::Circle, Some('F')),
+    ("090093", ProductType::Circle, Some('F')),
+    ("090000", ProductType::CirclePlus, Some('B')),
+    ("090007", ProductType::CirclePlus, Some('B')),
+    ("090088", ProductType::CirclePlus, Some('E')),
+];
 
 /// Convert log element to memory address
 fn pos2addr(pos: u32) -> u32 {
@@ -17,14 +30,15 @@ fn addr2pos(addr: u32) -> u32 {
     (addr - ADDR_OFFS) / BYTES_PER_POS
 }
 
+/// Number of pulses over a timespan (in seconds); negative pulses represent produced power
 #[derive(Debug, Copy, Clone)]
 pub struct Pulses {
-    pulses: u32,
+    pulses: i64,
     timespan: u32
 }
 
 impl Pulses {
-    pub fn new(pulses: u32, timespan: u32) -> Pulses {
+    pub fn new(pulses: i64, timespan: u32) -> Pulses {
         Pulses {
             pulses: pulses,
             timespan: timespan
@@ -33,16 +47,27 @@ impl Pulses {
 
     /// Retrieve corrected number of pulses per second
     fn to_pulses_per_second(&self, calibration: ResCalibration) -> f64 {
-        if self.pulses == 0 || self.pulses == 0xffff {
+        if self.pulses == 0 {
             0.0
         } else {
-            let noise_corrected = (self.pulses as f64 / self.timespan as f64) +
+            // the correction applies to the magnitude of the pulses (regardless of the direction)
+            let noise_corrected = (self.pulses.abs() as f64 / self.timespan as f64) +
                 calibration.off_noise as f64;
-            (noise_corrected.powi(2) * calibration.gain_b as f64) +
-                (noise_corrected * calibration.gain_a as f64) + calibration.off_total as f64
+            let corrected = (noise_corrected.powi(2) * calibration.gain_b as f64) +
+                (noise_corrected * calibration.gain_a as f64) + calibration.off_total as f64;
+            if self.pulses < 0 {
+                -corrected
+            } else {
+                corrected
+            }
         }
     }
 
+    /// Change the timespan depending on the direction of the pulses
+    fn with_interval(&self, consumption: u32, production: u32) -> Pulses {
+        Pulses::new(self.pulses, if self.pulses < 0 { production } else { consumption })
+    }
+
     /// Convert pulses to kW
     fn to_kw(&self, calibration: ResCalibration) -> f64 {
         let pulses = self.to_pulses_per_second(calibration);
@@ -78,9 +103,96 @@ impl ReqHeader {
     }
 }
 
+/// Status code of an acknowledge of the Plugwise USB stick or a Circle
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AckStatus {
+    /// Request accepted by the USB stick
+    Success,
+    /// Request rejected by the USB stick
+    Nack,
+    /// No response of the addressed node
+    Timeout,
+    /// Relay of the Circle has been switched on
+    RelaySwitchedOn,
+    /// Relay of the Circle has been switched off
+    RelaySwitchedOff,
+    /// Relay of the Circle could not be switched
+    RelaySwitchFailed,
+    /// Clock of the Circle has been set
+    ClockAccepted,
+    /// Node has been joined to the network
+    JoinAccepted,
+    /// Real-time clock of the Circle+ has been set
+    RealTimeClockAccepted,
+    /// Real-time clock of the Circle+ could not be set
+    RealTimeClockFailed,
+    /// Log interval of the Circle has been set
+    LogIntervalAccepted,
+    /// Relay lock of the Circle has been changed
+    RelayLockAccepted,
+    /// Part of the switching schedule of the Circle has been stored (or the schedule has been
+    /// enabled or disabled)
+    ScheduleAccepted,
+    /// Sleep configuration of a sleeping device has been accepted
+    SleepConfigAccepted,
+    /// Sleep configuration of a sleeping device has been rejected
+    SleepConfigFailed,
+    /// Report interval of a Sense has been accepted
+    SenseIntervalAccepted,
+    /// Report interval of a Sense has been rejected
+    SenseIntervalFailed,
+    /// Configuration of a Scan has been accepted
+    ScanConfigAccepted,
+    /// Configuration of a Scan has been rejected
+    ScanConfigFailed,
+    /// Status code not known by this crate
+    Unknown(u16)
+}
+
+impl AckStatus {
+    fn new(status: u16) -> AckStatus {
+        match status {
+            ACK_SUCCESS => AckStatus::Success,
+            ACK_NACK => AckStatus::Nack,
+            ACK_TIMEOUT => AckStatus::Timeout,
+            ACK_RELAY_ON => AckStatus::RelaySwitchedOn,
+            ACK_RELAY_OFF => AckStatus::RelaySwitchedOff,
+            ACK_RELAY_FAILED => AckStatus::RelaySwitchFailed,
+            ACK_CLOCK_ACCEPTED => AckStatus::ClockAccepted,
+            ACK_JOIN_ACCEPTED => AckStatus::JoinAccepted,
+            ACK_RTC_ACCEPTED => AckStatus::RealTimeClockAccepted,
+            ACK_RTC_FAILED => AckStatus::RealTimeClockFailed,
+            ACK_LOG_INTERVAL_ACCEPTED => AckStatus::LogIntervalAccepted,
+            ACK_RELAY_LOCK_ACCEPTED => AckStatus::RelayLockAccepted,
+            ACK_SCHEDULE_ACCEPTED => AckStatus::ScheduleAccepted,
+            ACK_SLEEP_CONFIG_ACCEPTED => AckStatus::SleepConfigAccepted,
+            ACK_SLEEP_CONFIG_FAILED => AckStatus::SleepConfigFailed,
+            ACK_SENSE_INTERVAL_ACCEPTED => AckStatus::SenseIntervalAccepted,
+            ACK_SENSE_INTERVAL_FAILED => AckStatus::SenseIntervalFailed,
+            ACK_SCAN_CONFIG_ACCEPTED => AckStatus::ScanConfigAccepted,
+            ACK_SCAN_CONFIG_FAILED => AckStatus::ScanConfigFailed,
+            _ => AckStatus::Unknown(status)
+        }
+    }
+
+    /// Indicates whether the status reports a failure
+    pub fn is_nack(&self) -> bool {
+        match *self {
+            AckStatus::Nack |
+            AckStatus::Timeout |
+            AckStatus::RelaySwitchFailed |
+            AckStatus::RealTimeClockFailed |
+            AckStatus::SleepConfigFailed |
+            AckStatus::SenseIntervalFailed |
+            AckStatus::ScanConfigFailed => true,
+            _ => false
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Ack {
-    pub status: u16,
+    pub status: AckStatus,
     pub mac: Option<u64>
 }
 
@@ -97,7 +209,7 @@ impl Ack {
         try!(decoder.check_fully_consumed());
 
         Ok(Ack {
-            status: status,
+            status: AckStatus::new(status),
             mac: mac
         })
     }
@@ -105,6 +217,7 @@ impl Ack {
 
 #[derive(Debug, Copy, Clone)]
 pub struct ResInitialize {
+    pub mac: u64,
     pub unknown1: u8,
     pub is_online: bool,
     pub network_id: u64,
@@ -113,8 +226,10 @@ pub struct ResInitialize {
 }
 
 impl ResInitialize {
-    /// Decode initialization response
-    fn new(decoder: raw::RawDataConsumer) -> error::PlResult<ResInitialize> {
+    /// Decode initialization response (of which the header contains the address of the USB stick
+    /// itself). The meaning of `unknown1` and `unknown2` is not known yet, therefore these fields
+    /// are kept undecoded.
+    fn new(mac: u64, decoder: raw::RawDataConsumer) -> error::PlResult<ResInitialize> {
         let (decoder, unknown1) = try!(decoder.decode::<u8>());
         let (decoder, is_online) = try!(decoder.decode::<u8>());
         let (decoder, network_id) = try!(decoder.decode::<u64>());
@@ -123,6 +238,7 @@ impl ResInitialize {
         try!(decoder.check_fully_consumed());
 
         Ok(ResInitialize {
+            mac: mac,
             unknown1: unknown1,
             is_online: is_online != 0,
             network_id: network_id,
@@ -163,7 +279,7 @@ impl DateTime {
         let hours = ((self.minutes / 60) % 24) as i32;
         let mday = 1 + (self.minutes / (24 * 60)) as i32;
 
-        if self.months > 12 || mday > 31 {
+        if self.months == 0 || self.months > 12 || mday > 31 {
             return None;
         }
 
@@ -185,15 +301,37 @@ impl DateTime {
     }
 }
 
+/// Kind of Plugwise product
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ProductType {
+    /// USB stick
+    Stick,
+    /// Circle+ (coordinator of the network)
+    CirclePlus,
+    /// Circle (wall outlet switch)
+    Circle,
+    /// Switch (battery powered wall switch)
+    Switch,
+    /// Sense (temperature and humidity sensor)
+    Sense,
+    /// Scan (motion sensor)
+    Scan,
+    /// Stealth (built-in switch)
+    Stealth,
+    /// Product not known by this crate
+    Unknown(u8)
+}
+
 #[derive(Debug, Clone)]
 pub struct ResInfo {
     pub datetime: DateTime,
     pub last_logaddr: u32,
     pub relay_state: bool,
+    pub relay_locked: bool,
     pub hz: u8,
     pub hw_ver: String,
     pub fw_ver: Timespec,
-    pub unknown: u8
+    pub node_type: ProductType
 }
 
 impl ResInfo {
@@ -205,13 +343,14 @@ impl ResInfo {
         let (decoder, hz) = try!(decoder.decode::<u8>());
         let (decoder, hw_ver) = try!(decoder.decode_string(12));
         let (decoder, fw_ver) = try!(decoder.decode::<u32>());
-        let (decoder, unknown) = try!(decoder.decode::<u8>());
+        let (decoder, node_type) = try!(decoder.decode::<u8>());
         try!(decoder.check_fully_consumed());
 
         Ok(ResInfo {
             datetime: datetime,
             last_logaddr: addr2pos(last_logaddr),
-            relay_state: relay_state != 0,
+            relay_state: relay_state & RELAY_ON != 0,
+            relay_locked: relay_state & RELAY_LOCKED != 0,
             hz: match hz {
                 133 => 50,
                 197 => 60,
@@ -219,9 +358,31 @@ impl ResInfo {
             },
             hw_ver: hw_ver.to_string(),
             fw_ver: Timespec::new((fw_ver as i32) as i64, 0),
-            unknown: unknown
+            node_type: match node_type {
+                0 => ProductType::Stick,
+                1 => ProductType::CirclePlus,
+                2 => ProductType::Circle,
+                3 => ProductType::Switch,
+                5 => ProductType::Sense,
+                6 => ProductType::Scan,
+                9 => ProductType::Stealth,
+                n => ProductType::Unknown(n)
+            }
         })
     }
+
+    /// Look up the product type and hardware revision by the model code within the hardware
+    /// version (`None` when the model is not known)
+    pub fn get_model(&self) -> Option<(ProductType, Option<char>)> {
+        if self.hw_ver.len() < 10 {
+            return None;
+        }
+        let code = &self.hw_ver.as_bytes()[4..10];
+
+        MODELS.iter()
+              .find(|&&(model, _, _)| model.as_bytes() == code)
+              .map(|&(_, product, revision)| (product, revision))
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -237,6 +398,374 @@ impl ReqSwitch {
     }
 }
 
+#[derive(Debug, Copy, Clone)]
+pub struct ReqRelayLock {
+    pub lock: bool
+}
+
+impl ReqRelayLock {
+    fn as_bytes(&self) -> Vec<u8> {
+        let lock = if self.lock {1} else {0};
+
+        format!("{:02X}", lock).bytes().collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ReqSchedule {
+    pub index: u32,
+    pub slots: [bool; 4]
+}
+
+impl ReqSchedule {
+    fn as_bytes(&self) -> Vec<u8> {
+        // the index counts the chunks of four slots from Monday 00:00 (it is not a memory address
+        // like the position in the power buffer)
+        let mut result = format!("{:08X}", self.index);
+
+        for slot in self.slots.iter() {
+            // -1 means on, 0 means off (other values are standby thresholds, which are unused)
+            result.push_str(if *slot { "FFFF" } else { "0000" });
+        }
+
+        result.bytes().collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ReqScheduleEnable {
+    pub enable: bool
+}
+
+impl ReqScheduleEnable {
+    fn as_bytes(&self) -> Vec<u8> {
+        let enable = if self.enable {1} else {0};
+
+        format!("{:02X}", enable).bytes().collect()
+    }
+}
+
+/// Reason why a sleeping device announces it is awake
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AwakeReason {
+    /// Periodic maintenance
+    Maintenance,
+    /// First announcement after the device has been powered
+    First,
+    /// Startup of the device
+    Startup,
+    /// State of the device has been changed (i.e. motion detected)
+    State,
+    /// Button of the device has been pressed
+    Button,
+    /// Reason not known by this crate
+    Unknown(u8)
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ResAwake {
+    pub reason: AwakeReason
+}
+
+impl ResAwake {
+    /// Decode awake announcement of a sleeping device
+    fn new(decoder: raw::RawDataConsumer) -> error::PlResult<ResAwake> {
+        let (decoder, reason) = try!(decoder.decode::<u8>());
+        try!(decoder.check_fully_consumed());
+
+        Ok(ResAwake {
+            reason: match reason {
+                0 => AwakeReason::Maintenance,
+                1 => AwakeReason::First,
+                2 => AwakeReason::Startup,
+                3 => AwakeReason::State,
+                5 => AwakeReason::Button,
+                n => AwakeReason::Unknown(n)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ResSwitchGroup {
+    pub group: u8,
+    pub on: bool
+}
+
+impl ResSwitchGroup {
+    /// Decode button press (Switch) or motion (Scan) notification
+    fn new(decoder: raw::RawDataConsumer) -> error::PlResult<ResSwitchGroup> {
+        let (decoder, group) = try!(decoder.decode::<u8>());
+        let (decoder, on) = try!(decoder.decode::<u8>());
+        try!(decoder.check_fully_consumed());
+
+        Ok(ResSwitchGroup {
+            group: group,
+            on: on != 0
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ResSenseReport {
+    pub humidity: f64,
+    pub temperature: f64
+}
+
+impl ResSenseReport {
+    /// Decode temperature and humidity report of a Sense
+    fn new(decoder: raw::RawDataConsumer) -> error::PlResult<ResSenseReport> {
+        let (decoder, humidity) = try!(decoder.decode::<u16>());
+        let (decoder, temperature) = try!(decoder.decode::<u16>());
+        try!(decoder.check_fully_consumed());
+
+        Ok(ResSenseReport {
+            humidity: 125.0 * (humidity as f64 / 65536.0) - 6.0,
+            temperature: 175.72 * (temperature as f64 / 65536.0) - 46.85
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ReqSleepConfig {
+    pub stay_active: u8,
+    pub maintenance_interval: u16,
+    pub sleep_for: u16
+}
+
+impl ReqSleepConfig {
+    fn as_bytes(&self) -> Vec<u8> {
+        // clock synchronization is disabled
+        format!("{:02X}{:04X}{:04X}000000",
+                self.stay_active, self.maintenance_interval, self.sleep_for).bytes().collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ReqSenseInterval {
+    pub interval: u8
+}
+
+impl ReqSenseInterval {
+    fn as_bytes(&self) -> Vec<u8> {
+        format!("{:02X}", self.interval).bytes().collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ReqScanConfig {
+    pub reset_timer: u8,
+    pub sensitivity: u8,
+    pub daylight: bool
+}
+
+impl ReqScanConfig {
+    fn as_bytes(&self) -> Vec<u8> {
+        let daylight = if self.daylight {1} else {0};
+
+        format!("{:02X}{:02X}{:02X}",
+                self.sensitivity, daylight, self.reset_timer).bytes().collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RealTimeClock {
+    pub second: u8,
+    pub minute: u8,
+    pub hour: u8,
+    pub day_of_week: u8,
+    pub day: u8,
+    pub month: u8,
+    pub year: u8
+}
+
+impl RealTimeClock {
+    pub fn new_from_tm(tm: Tm) -> RealTimeClock {
+        let utc = tm.to_utc();
+        let day_of_week = match utc.tm_wday {
+            n @ 1...6 => n as u8,
+            0 => 7 as u8,
+            _ => unreachable!()
+        };
+
+        RealTimeClock {
+            second: utc.tm_sec as u8,
+            minute: utc.tm_min as u8,
+            hour: utc.tm_hour as u8,
+            day_of_week: day_of_week,
+            day: utc.tm_mday as u8,
+            month: (utc.tm_mon + 1) as u8,
+            year: (utc.tm_year - 100) as u8
+        }
+    }
+
+    /// Decode real-time clock response
+    fn new(decoder: raw::RawDataConsumer) -> error::PlResult<RealTimeClock> {
+        let (decoder, second) = try!(decoder.decode_decimal());
+        let (decoder, minute) = try!(decoder.decode_decimal());
+        let (decoder, hour) = try!(decoder.decode_decimal());
+        let (decoder, day_of_week) = try!(decoder.decode::<u8>());
+        let (decoder, day) = try!(decoder.decode_decimal());
+        let (decoder, month) = try!(decoder.decode_decimal());
+        let (decoder, year) = try!(decoder.decode_decimal());
+        try!(decoder.check_fully_consumed());
+
+        Ok(RealTimeClock {
+            second: second,
+            minute: minute,
+            hour: hour,
+            day_of_week: day_of_week,
+            day: day,
+            month: month,
+            year: year
+        })
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+        format!("{:02}{:02}{:02}{:02X}{:02}{:02}{:02}",
+                self.second, self.minute, self.hour, self.day_of_week,
+                self.day, self.month, self.year).bytes().collect()
+    }
+
+    pub fn to_tm(&self) -> Option<Tm> {
+        if self.second > 59 || self.minute > 59 || self.hour > 23 || self.day_of_week > 7 ||
+           self.day < 1 || self.day > 31 || self.month < 1 || self.month > 12 {
+            return None;
+        }
+
+        let tm = Tm {
+            tm_sec: self.second as i32,
+            tm_min: self.minute as i32,
+            tm_hour: self.hour as i32,
+            tm_mday: self.day as i32,
+            tm_mon: (self.month - 1) as i32,
+            tm_year: 100 + (self.year) as i32,
+            tm_wday: (self.day_of_week % 7) as i32,
+            tm_yday: 0,
+            tm_isdst: 0,
+            tm_utcoff: 0,
+            tm_nsec: 0
+        };
+
+        Some(tm)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ReqLinkedNode {
+    pub index: u8
+}
+
+impl ReqLinkedNode {
+    fn as_bytes(&self) -> Vec<u8> {
+        format!("{:02X}", self.index).bytes().collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ResLinkedNode {
+    pub mac: Option<u64>,
+    pub index: u8
+}
+
+impl ResLinkedNode {
+    /// Decode node table entry of the Circle+
+    fn new(decoder: raw::RawDataConsumer) -> error::PlResult<ResLinkedNode> {
+        let (decoder, mac) = try!(decoder.decode::<u64>());
+        let (decoder, index) = try!(decoder.decode::<u8>());
+        try!(decoder.check_fully_consumed());
+
+        Ok(ResLinkedNode {
+            mac: match mac {
+                0xffffffffffffffff => None, // unused entry
+                mac => Some(mac)
+            },
+            index: index
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ResPing {
+    pub rssi_in: u8,
+    pub rssi_out: u8,
+    pub round_trip: u16
+}
+
+impl ResPing {
+    /// Decode ping response
+    fn new(decoder: raw::RawDataConsumer) -> error::PlResult<ResPing> {
+        let (decoder, rssi_in) = try!(decoder.decode::<u8>());
+        let (decoder, rssi_out) = try!(decoder.decode::<u8>());
+        let (decoder, round_trip) = try!(decoder.decode::<u16>());
+        try!(decoder.check_fully_consumed());
+
+        Ok(ResPing {
+            rssi_in: rssi_in,
+            rssi_out: rssi_out,
+            round_trip: round_trip
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ReqJoinMode {
+    pub enable: bool
+}
+
+impl ReqJoinMode {
+    fn as_bytes(&self) -> Vec<u8> {
+        let enable = if self.enable {1} else {0};
+
+        format!("{:02X}", enable).bytes().collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ReqJoinAccept {
+    pub mac: u64,
+    pub accept: bool
+}
+
+impl ReqJoinAccept {
+    fn as_bytes(&self) -> Vec<u8> {
+        let accept = if self.accept {1} else {0};
+
+        format!("{:02X}{:016X}", accept, self.mac).bytes().collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ReqRemoveNode {
+    pub mac: u64
+}
+
+impl ReqRemoveNode {
+    fn as_bytes(&self) -> Vec<u8> {
+        format!("{:016X}", self.mac).bytes().collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ResRemoveNode {
+    pub mac: u64,
+    pub removed: bool
+}
+
+impl ResRemoveNode {
+    /// Decode node removal response of the Circle+
+    fn new(decoder: raw::RawDataConsumer) -> error::PlResult<ResRemoveNode> {
+        let (decoder, mac) = try!(decoder.decode::<u64>());
+        let (decoder, removed) = try!(decoder.decode::<u8>());
+        try!(decoder.check_fully_consumed());
+
+        Ok(ResRemoveNode {
+            mac: mac,
+            removed: removed != 0
+        })
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct ResCalibration {
     pub gain_a: f32,
@@ -303,16 +832,37 @@ impl ResPowerBuffer {
 
         Ok(ResPowerBuffer {
             datetime1: datetime1,
-            pulses1: Pulses::new(pulses1, 3600),
+            pulses1: Pulses::new(pulses1 as i32 as i64, DEFAULT_LOG_INTERVAL_SECS),
             datetime2: datetime2,
-            pulses2: Pulses::new(pulses2, 3600),
+            pulses2: Pulses::new(pulses2 as i32 as i64, DEFAULT_LOG_INTERVAL_SECS),
             datetime3: datetime3,
-            pulses3: Pulses::new(pulses3, 3600),
+            pulses3: Pulses::new(pulses3 as i32 as i64, DEFAULT_LOG_INTERVAL_SECS),
             datetime4: datetime4,
-            pulses4: Pulses::new(pulses4, 3600),
+            pulses4: Pulses::new(pulses4 as i32 as i64, DEFAULT_LOG_INTERVAL_SECS),
             logaddr: addr2pos(logaddr)
         })
     }
+
+    /// Change the timespan (in seconds) covered by each logged element (consumed and produced
+    /// power might be logged with a different interval)
+    pub fn set_interval(&mut self, consumption: u32, production: u32) {
+        self.pulses1 = self.pulses1.with_interval(consumption, production);
+        self.pulses2 = self.pulses2.with_interval(consumption, production);
+        self.pulses3 = self.pulses3.with_interval(consumption, production);
+        self.pulses4 = self.pulses4.with_interval(consumption, production);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ReqLogInterval {
+    pub consumption: u16,
+    pub production: u16
+}
+
+impl ReqLogInterval {
+    fn as_bytes(&self) -> Vec<u8> {
+        format!("{:04X}{:04X}", self.consumption, self.production).bytes().collect()
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -320,9 +870,8 @@ pub struct ResPowerUse {
     pub pulse_1s: Pulses,
     pub pulse_8s: Pulses,
     pub pulse_hour: Pulses,
-    pub unknown1: u16,
-    pub unknown2: u16,
-    pub unknown3: u16,
+    pub pulse_hour_produced: Pulses,
+    pub unknown: u16,
 }
 
 impl ResPowerUse {
@@ -330,18 +879,17 @@ impl ResPowerUse {
         let (decoder, pulse_1s) = try!(decoder.decode::<u16>());
         let (decoder, pulse_8s) = try!(decoder.decode::<u16>());
         let (decoder, pulse_hour) = try!(decoder.decode::<u32>());
-        let (decoder, unknown1) = try!(decoder.decode::<u16>());
-        let (decoder, unknown2) = try!(decoder.decode::<u16>());
-        let (decoder, unknown3) = try!(decoder.decode::<u16>());
+        let (decoder, pulse_hour_produced) = try!(decoder.decode::<u32>());
+        let (decoder, unknown) = try!(decoder.decode::<u16>());
         try!(decoder.check_fully_consumed());
 
         Ok(ResPowerUse {
-            pulse_1s: Pulses::new(pulse_1s as u32, 1),
-            pulse_8s: Pulses::new(pulse_8s as u32, 8),
-            pulse_hour: Pulses::new(pulse_hour, 3600),
-            unknown1: unknown1,
-            unknown2: unknown2,
-            unknown3: unknown3,
+            // actual pulses are signed (negative when power is produced)
+            pulse_1s: Pulses::new(pulse_1s as i16 as i64, 1),
+            pulse_8s: Pulses::new(pulse_8s as i16 as i64, 8),
+            pulse_hour: Pulses::new(pulse_hour as i64, 3600),
+            pulse_hour_produced: Pulses::new(-(pulse_hour_produced as i64), 3600),
+            unknown: unknown,
         })
     }
 }
@@ -418,6 +966,26 @@ impl ReqClockSet {
     }
 }
 
+const ACK_SENSE_INTERVAL_ACCEPTED: u16 = 0x00B3;
+const ACK_SENSE_INTERVAL_FAILED: u16 = 0x00B4;
+const ACK_SCAN_CONFIG_ACCEPTED: u16 = 0x00BE;
+const ACK_SCAN_CONFIG_FAILED: u16 = 0x00BF;
+const ACK_SUCCESS: u16 = 0x00C1;
+const ACK_NACK: u16 = 0x00C2;
+const ACK_CLOCK_ACCEPTED: u16 = 0x00D7;
+const ACK_RELAY_ON: u16 = 0x00D8;
+const ACK_JOIN_ACCEPTED: u16 = 0x00D9;
+const ACK_RELAY_OFF: u16 = 0x00DE;
+const ACK_RTC_ACCEPTED: u16 = 0x00DF;
+const ACK_TIMEOUT: u16 = 0x00E1;
+const ACK_RELAY_FAILED: u16 = 0x00E2;
+const ACK_RTC_FAILED: u16 = 0x00E7;
+const ACK_RELAY_LOCK_ACCEPTED: u16 = 0x00F1;
+const ACK_SLEEP_CONFIG_ACCEPTED: u16 = 0x00F6;
+const ACK_SLEEP_CONFIG_FAILED: u16 = 0x00F7;
+const ACK_LOG_INTERVAL_ACCEPTED: u16 = 0x00F8;
+const ACK_SCHEDULE_ACCEPTED: u16 = 0x00F9;
+
 const ACK: u16 = 0x0000;
 const REQ_INITIALIZE: u16 = 0x000A;
 const RES_INITIALIZE: u16 = 0x0011;
@@ -433,25 +1001,69 @@ const RES_POWER_USE: u16 = 0x0013;
 const REQ_CLOCK_INFO: u16 = 0x003E;
 const RES_CLOCK_INFO: u16 = 0x003F;
 const REQ_CLOCK_SET: u16 = 0x0016;
+const REQ_LINKED_NODE: u16 = 0x0018;
+const RES_LINKED_NODE: u16 = 0x0019;
+const REQ_PING: u16 = 0x000D;
+const RES_PING: u16 = 0x000E;
+const RES_JOIN_REQUEST: u16 = 0x0006;
+const REQ_JOIN_ACCEPT: u16 = 0x0007;
+const REQ_JOIN_MODE: u16 = 0x0008;
+const REQ_REMOVE_NODE: u16 = 0x001C;
+const RES_REMOVE_NODE: u16 = 0x001D;
+const REQ_REALTIME_CLOCK_SET: u16 = 0x0028;
+const REQ_REALTIME_CLOCK: u16 = 0x0029;
+const RES_REALTIME_CLOCK: u16 = 0x003A;
+const REQ_LOG_INTERVAL: u16 = 0x0057;
+const REQ_RELAY_LOCK: u16 = 0x004E;
+const REQ_SCHEDULE: u16 = 0x0058;
+const REQ_SCHEDULE_ENABLE: u16 = 0x0040;
+const RES_AWAKE: u16 = 0x004F;
+const REQ_SLEEP_CONFIG: u16 = 0x0050;
+const RES_SWITCH_GROUP: u16 = 0x0056;
+const REQ_SCAN_CONFIG: u16 = 0x0101;
+const REQ_SENSE_INTERVAL: u16 = 0x0103;
+const RES_SENSE_REPORT: u16 = 0x0105;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-#[repr(u16)]
 pub enum MessageId {
-    Ack = ACK,
-    ReqInitialize = REQ_INITIALIZE,
-    ResInitialize = RES_INITIALIZE,
-    ReqInfo = REQ_INFO,
-    ResInfo = RES_INFO,
-    ReqSwitch = REQ_SWITCH,
-    ReqCalibration = REQ_CALIBRATION,
-    ResCalibration = RES_CALIBRATION,
-    ReqPowerBuffer = REQ_POWER_BUFFER,
-    ResPowerBuffer = RES_POWER_BUFFER,
-    ReqPowerUse = REQ_POWER_USE,
-    ResPowerUse = RES_POWER_USE,
-    ReqClockInfo = REQ_CLOCK_INFO,
-    ResClockInfo = RES_CLOCK_INFO,
-    ReqClockSet = REQ_CLOCK_SET,
+    Ack,
+    ReqInitialize,
+    ResInitialize,
+    ReqInfo,
+    ResInfo,
+    ReqSwitch,
+    ReqCalibration,
+    ResCalibration,
+    ReqPowerBuffer,
+    ResPowerBuffer,
+    ReqPowerUse,
+    ResPowerUse,
+    ReqClockInfo,
+    ResClockInfo,
+    ReqClockSet,
+    ReqLinkedNode,
+    ResLinkedNode,
+    ReqPing,
+    ResPing,
+    ResJoinRequest,
+    ReqJoinAccept,
+    ReqJoinMode,
+    ReqRemoveNode,
+    ResRemoveNode,
+    ReqRealTimeClockSet,
+    ReqRealTimeClock,
+    ResRealTimeClock,
+    ReqLogInterval,
+    ReqRelayLock,
+    ReqSchedule,
+    ReqScheduleEnable,
+    ResAwake,
+    ReqSleepConfig,
+    ResSwitchGroup,
+    ReqScanConfig,
+    ReqSenseInterval,
+    ResSenseReport,
+    Unknown(u16),
 }
 
 impl MessageId {
@@ -471,12 +1083,89 @@ impl MessageId {
             REQ_CLOCK_INFO => MessageId::ReqClockInfo,
             RES_CLOCK_INFO => MessageId::ResClockInfo,
             REQ_CLOCK_SET => MessageId::ReqClockSet,
-            ACK|_ => MessageId::Ack,
+            REQ_LINKED_NODE => MessageId::ReqLinkedNode,
+            RES_LINKED_NODE => MessageId::ResLinkedNode,
+            REQ_PING => MessageId::ReqPing,
+            RES_PING => MessageId::ResPing,
+            RES_JOIN_REQUEST => MessageId::ResJoinRequest,
+            REQ_JOIN_ACCEPT => MessageId::ReqJoinAccept,
+            REQ_JOIN_MODE => MessageId::ReqJoinMode,
+            REQ_REMOVE_NODE => MessageId::ReqRemoveNode,
+            RES_REMOVE_NODE => MessageId::ResRemoveNode,
+            REQ_REALTIME_CLOCK_SET => MessageId::ReqRealTimeClockSet,
+            REQ_REALTIME_CLOCK => MessageId::ReqRealTimeClock,
+            RES_REALTIME_CLOCK => MessageId::ResRealTimeClock,
+            REQ_LOG_INTERVAL => MessageId::ReqLogInterval,
+            REQ_RELAY_LOCK => MessageId::ReqRelayLock,
+            REQ_SCHEDULE => MessageId::ReqSchedule,
+            REQ_SCHEDULE_ENABLE => MessageId::ReqScheduleEnable,
+            RES_AWAKE => MessageId::ResAwake,
+            REQ_SLEEP_CONFIG => MessageId::ReqSleepConfig,
+            RES_SWITCH_GROUP => MessageId::ResSwitchGroup,
+            REQ_SCAN_CONFIG => MessageId::ReqScanConfig,
+            REQ_SENSE_INTERVAL => MessageId::ReqSenseInterval,
+            RES_SENSE_REPORT => MessageId::ResSenseReport,
+            ACK => MessageId::Ack,
+            n => MessageId::Unknown(n),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            MessageId::Ack => ACK,
+            MessageId::ReqInitialize => REQ_INITIALIZE,
+            MessageId::ResInitialize => RES_INITIALIZE,
+            MessageId::ReqInfo => REQ_INFO,
+            MessageId::ResInfo => RES_INFO,
+            MessageId::ReqSwitch => REQ_SWITCH,
+            MessageId::ReqCalibration => REQ_CALIBRATION,
+            MessageId::ResCalibration => RES_CALIBRATION,
+            MessageId::ReqPowerBuffer => REQ_POWER_BUFFER,
+            MessageId::ResPowerBuffer => RES_POWER_BUFFER,
+            MessageId::ReqPowerUse => REQ_POWER_USE,
+            MessageId::ResPowerUse => RES_POWER_USE,
+            MessageId::ReqClockInfo => REQ_CLOCK_INFO,
+            MessageId::ResClockInfo => RES_CLOCK_INFO,
+            MessageId::ReqClockSet => REQ_CLOCK_SET,
+            MessageId::ReqLinkedNode => REQ_LINKED_NODE,
+            MessageId::ResLinkedNode => RES_LINKED_NODE,
+            MessageId::ReqPing => REQ_PING,
+            MessageId::ResPing => RES_PING,
+            MessageId::ResJoinRequest => RES_JOIN_REQUEST,
+            MessageId::ReqJoinAccept => REQ_JOIN_ACCEPT,
+            MessageId::ReqJoinMode => REQ_JOIN_MODE,
+            MessageId::ReqRemoveNode => REQ_REMOVE_NODE,
+            MessageId::ResRemoveNode => RES_REMOVE_NODE,
+            MessageId::ReqRealTimeClockSet => REQ_REALTIME_CLOCK_SET,
+            MessageId::ReqRealTimeClock => REQ_REALTIME_CLOCK,
+            MessageId::ResRealTimeClock => RES_REALTIME_CLOCK,
+            MessageId::ReqLogInterval => REQ_LOG_INTERVAL,
+            MessageId::ReqRelayLock => REQ_RELAY_LOCK,
+            MessageId::ReqSchedule => REQ_SCHEDULE,
+            MessageId::ReqScheduleEnable => REQ_SCHEDULE_ENABLE,
+            MessageId::ResAwake => RES_AWAKE,
+            MessageId::ReqSleepConfig => REQ_SLEEP_CONFIG,
+            MessageId::ResSwitchGroup => RES_SWITCH_GROUP,
+            MessageId::ReqScanConfig => REQ_SCAN_CONFIG,
+            MessageId::ReqSenseInterval => REQ_SENSE_INTERVAL,
+            MessageId::ResSenseReport => RES_SENSE_REPORT,
+            MessageId::Unknown(n) => n,
         }
     }
 
     fn as_bytes(&self) -> Vec<u8> {
-        format!("{:04X}", *self as u16).bytes().collect()
+        format!("{:04X}", self.to_u16()).bytes().collect()
+    }
+
+    /// Indicates whether the message is sent without being requested
+    pub fn is_unsolicited(&self) -> bool {
+        match *self {
+            MessageId::ResJoinRequest |
+            MessageId::ResAwake |
+            MessageId::ResSwitchGroup |
+            MessageId::ResSenseReport => true,
+            _ => false
+        }
     }
 }
 
@@ -497,6 +1186,29 @@ pub enum Message {
     ReqClockInfo(ReqHeader),
     ResClockInfo(ResHeader, ResClockInfo),
     ReqClockSet(ReqHeader, ReqClockSet),
+    ReqLinkedNode(ReqHeader, ReqLinkedNode),
+    ResLinkedNode(ResHeader, ResLinkedNode),
+    ReqPing(ReqHeader),
+    ResPing(ResHeader, ResPing),
+    ResJoinRequest(ResHeader),
+    ReqJoinAccept(ReqJoinAccept),
+    ReqJoinMode(ReqJoinMode),
+    ReqRemoveNode(ReqHeader, ReqRemoveNode),
+    ResRemoveNode(ResHeader, ResRemoveNode),
+    ReqRealTimeClockSet(ReqHeader, RealTimeClock),
+    ReqRealTimeClock(ReqHeader),
+    ResRealTimeClock(ResHeader, RealTimeClock),
+    ReqLogInterval(ReqHeader, ReqLogInterval),
+    ReqRelayLock(ReqHeader, ReqRelayLock),
+    ReqSchedule(ReqHeader, ReqSchedule),
+    ReqScheduleEnable(ReqHeader, ReqScheduleEnable),
+    ResAwake(ResHeader, ResAwake),
+    ReqSleepConfig(ReqHeader, ReqSleepConfig),
+    ResSwitchGroup(ResHeader, ResSwitchGroup),
+    ReqScanConfig(ReqHeader, ReqScanConfig),
+    ReqSenseInterval(ReqHeader, ReqSenseInterval),
+    ResSenseReport(ResHeader, ResSenseReport),
+    Unknown(ResHeader, Vec<u8>),
 }
 
 impl Message {
@@ -507,15 +1219,8 @@ impl Message {
         vec.extend(self.to_message_id().as_bytes());
 
         // handle header (generically)
-        match *self {
-            Message::ReqInfo(header) |
-            Message::ReqSwitch(header, _) |
-            Message::ReqCalibration(header) |
-            Message::ReqPowerBuffer(header, _) |
-            Message::ReqPowerUse(header) |
-            Message::ReqClockInfo(header) |
-            Message::ReqClockSet(header, _) => vec.extend(header.as_bytes()),
-            _ => {}
+        if let Some(mac) = self.get_destination_mac() {
+            vec.extend(ReqHeader{mac: mac}.as_bytes());
         }
 
         match *self {
@@ -523,7 +1228,9 @@ impl Message {
             Message::ReqInfo(_) |
             Message::ReqCalibration(_) |
             Message::ReqPowerUse(_) |
-            Message::ReqClockInfo(_) => Ok(vec),
+            Message::ReqClockInfo(_) |
+            Message::ReqPing(_) |
+            Message::ReqRealTimeClock(_) => Ok(vec),
             Message::ReqPowerBuffer(_, req) => {
                 vec.extend(req.as_bytes());
                 Ok(vec)
@@ -536,6 +1243,54 @@ impl Message {
                 vec.extend(req.as_bytes());
                 Ok(vec)
             },
+            Message::ReqLinkedNode(_, req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqJoinAccept(req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqJoinMode(req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqRemoveNode(_, req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqRealTimeClockSet(_, req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqLogInterval(_, req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqRelayLock(_, req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqSchedule(_, req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqScheduleEnable(_, req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqSleepConfig(_, req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqScanConfig(_, req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
+            Message::ReqSenseInterval(_, req) => {
+                vec.extend(req.as_bytes());
+                Ok(vec)
+            },
             _ => Err(error::PlError::Protocol)
         }
     }
@@ -548,10 +1303,11 @@ impl Message {
         let (decoder, counter) = try!(decoder.decode::<u16>());
         let msg_id = MessageId::new(msg_id);
 
-        let (decoder, mac) = if msg_id != MessageId::Ack {
-            try!(decoder.decode::<u64>())
-        } else {
-            (decoder, 0)
+        let (decoder, mac) = match msg_id {
+            MessageId::Ack => (decoder, 0),
+            // it is not known whether unknown messages contain an address
+            MessageId::Unknown(_) if decoder.remainder().len() < 16 => (decoder, 0),
+            _ => try!(decoder.decode::<u64>())
         };
 
         let header = ResHeader {
@@ -562,7 +1318,7 @@ impl Message {
 
         match msg_id {
             MessageId::ResInitialize =>
-                Ok(Message::ResInitialize(header, try!(ResInitialize::new(decoder)))),
+                Ok(Message::ResInitialize(header, try!(ResInitialize::new(header.mac, decoder)))),
             MessageId::ResInfo =>
                 Ok(Message::ResInfo(header, try!(ResInfo::new(decoder)))),
             MessageId::ResCalibration =>
@@ -573,8 +1329,28 @@ impl Message {
                 Ok(Message::ResPowerUse(header, try!(ResPowerUse::new(decoder)))),
             MessageId::ResClockInfo =>
                 Ok(Message::ResClockInfo(header, try!(ResClockInfo::new(decoder)))),
+            MessageId::ResLinkedNode =>
+                Ok(Message::ResLinkedNode(header, try!(ResLinkedNode::new(decoder)))),
+            MessageId::ResPing =>
+                Ok(Message::ResPing(header, try!(ResPing::new(decoder)))),
+            MessageId::ResRemoveNode =>
+                Ok(Message::ResRemoveNode(header, try!(ResRemoveNode::new(decoder)))),
+            MessageId::ResRealTimeClock =>
+                Ok(Message::ResRealTimeClock(header, try!(RealTimeClock::new(decoder)))),
+            MessageId::ResAwake =>
+                Ok(Message::ResAwake(header, try!(ResAwake::new(decoder)))),
+            MessageId::ResSwitchGroup =>
+                Ok(Message::ResSwitchGroup(header, try!(ResSwitchGroup::new(decoder)))),
+            MessageId::ResSenseReport =>
+                Ok(Message::ResSenseReport(header, try!(ResSenseReport::new(decoder)))),
+            MessageId::ResJoinRequest => {
+                try!(decoder.check_fully_consumed());
+                Ok(Message::ResJoinRequest(header))
+            },
             MessageId::Ack =>
                 Ok(Message::Ack(header, try!(Ack::new(decoder)))),
+            MessageId::Unknown(_) =>
+                Ok(Message::Unknown(header, decoder.remainder().to_vec())),
             _ =>
                 Err(error::PlError::Protocol)
         }
@@ -597,6 +1373,90 @@ impl Message {
             Message::ReqClockInfo(..) => MessageId::ReqClockInfo,
             Message::ResClockInfo(..) => MessageId::ResClockInfo,
             Message::ReqClockSet(..) => MessageId::ReqClockSet,
+            Message::ReqLinkedNode(..) => MessageId::ReqLinkedNode,
+            Message::ResLinkedNode(..) => MessageId::ResLinkedNode,
+            Message::ReqPing(..) => MessageId::ReqPing,
+            Message::ResPing(..) => MessageId::ResPing,
+            Message::ResJoinRequest(..) => MessageId::ResJoinRequest,
+            Message::ReqJoinAccept(..) => MessageId::ReqJoinAccept,
+            Message::ReqJoinMode(..) => MessageId::ReqJoinMode,
+            Message::ReqRemoveNode(..) => MessageId::ReqRemoveNode,
+            Message::ResRemoveNode(..) => MessageId::ResRemoveNode,
+            Message::ReqRealTimeClockSet(..) => MessageId::ReqRealTimeClockSet,
+            Message::ReqRealTimeClock(..) => MessageId::ReqRealTimeClock,
+            Message::ResRealTimeClock(..) => MessageId::ResRealTimeClock,
+            Message::ReqLogInterval(..) => MessageId::ReqLogInterval,
+            Message::ReqRelayLock(..) => MessageId::ReqRelayLock,
+            Message::ReqSchedule(..) => MessageId::ReqSchedule,
+            Message::ReqScheduleEnable(..) => MessageId::ReqScheduleEnable,
+            Message::ResAwake(..) => MessageId::ResAwake,
+            Message::ReqSleepConfig(..) => MessageId::ReqSleepConfig,
+            Message::ResSwitchGroup(..) => MessageId::ResSwitchGroup,
+            Message::ReqScanConfig(..) => MessageId::ReqScanConfig,
+            Message::ReqSenseInterval(..) => MessageId::ReqSenseInterval,
+            Message::ResSenseReport(..) => MessageId::ResSenseReport,
+            Message::Unknown(header, _) => header.msgid,
+        }
+    }
+
+    /// Address of the device to which a request is sent (`None` for other messages, or requests
+    /// which are handled by the USB stick)
+    pub fn get_destination_mac(&self) -> Option<u64> {
+        match *self {
+            Message::ReqInfo(header) |
+            Message::ReqSwitch(header, _) |
+            Message::ReqCalibration(header) |
+            Message::ReqPowerBuffer(header, _) |
+            Message::ReqPowerUse(header) |
+            Message::ReqClockInfo(header) |
+            Message::ReqClockSet(header, _) |
+            Message::ReqLinkedNode(header, _) |
+            Message::ReqPing(header) |
+            Message::ReqRemoveNode(header, _) |
+            Message::ReqRealTimeClockSet(header, _) |
+            Message::ReqRealTimeClock(header) |
+            Message::ReqLogInterval(header, _) |
+            Message::ReqRelayLock(header, _) |
+            Message::ReqSchedule(header, _) |
+            Message::ReqScheduleEnable(header, _) |
+            Message::ReqSleepConfig(header, _) |
+            Message::ReqScanConfig(header, _) |
+            Message::ReqSenseInterval(header, _) => Some(header.mac),
+            _ => None
+        }
+    }
+
+    /// Header of a received message (`None` for requests)
+    pub fn get_header(&self) -> Option<ResHeader> {
+        match *self {
+            Message::Ack(header, _) |
+            Message::ResInitialize(header, _) |
+            Message::ResInfo(header, _) |
+            Message::ResCalibration(header, _) |
+            Message::ResPowerBuffer(header, _) |
+            Message::ResPowerUse(header, _) |
+            Message::ResClockInfo(header, _) |
+            Message::ResLinkedNode(header, _) |
+            Message::ResPing(header, _) |
+            Message::ResJoinRequest(header) |
+            Message::ResRemoveNode(header, _) |
+            Message::ResRealTimeClock(header, _) |
+            Message::ResAwake(header, _) |
+            Message::ResSwitchGroup(header, _) |
+            Message::ResSenseReport(header, _) |
+            Message::Unknown(header, _) => Some(header),
+            _ => None
+        }
+    }
+
+    /// Address of the device which sent an unsolicited message (`None` for other messages)
+    pub fn get_source_mac(&self) -> Option<u64> {
+        match *self {
+            Message::ResJoinRequest(header) |
+            Message::ResAwake(header, _) |
+            Message::ResSwitchGroup(header, _) |
+            Message::ResSenseReport(header, _) => Some(header.mac),
+            _ => None
         }
     }
 }
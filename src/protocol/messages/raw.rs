@@ -32,6 +32,11 @@ impl<'a> RawDataConsumer<'a> {
         }))
     }
 
+    /// Retrieve the part of the buffer which is not consumed yet
+    pub fn remainder(&self) -> &'a[u8] {
+        self.buf
+    }
+
     pub fn decode<T: Num>(&self) -> error::PlResult<(RawDataConsumer, T)> {
         let elements = mem::size_of::<T>() * 2;
         let (buf, result) = try!(self.consume(elements));
@@ -45,6 +50,17 @@ impl<'a> RawDataConsumer<'a> {
         Ok((result, value))
     }
 
+    /// Consume a decimal encoded `u8` (i.e. "59" means 59) from the buffer
+    pub fn decode_decimal(&self) -> error::PlResult<(RawDataConsumer, u8)> {
+        let (buf, result) = try!(self.consume(2));
+
+        let utf8 = unsafe {str::from_utf8_unchecked(buf)};
+        match u8::from_str_radix(utf8, 10) {
+            Err(_) => Err(error::PlError::Protocol),
+            Ok(n) => Ok((result, n))
+        }
+    }
+
     /// Consume a `f32` from the buffer
     pub fn decode_f32(&self) -> error::PlResult<(RawDataConsumer, f32)> {
         let (result, unconverted) = try!(self.decode::<u32>());
@@ -2,11 +2,21 @@ mod messages;
 
 use std::io;
 use std::io::prelude::*;
+use std::str;
+use std::cmp;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use crc16::*;
-pub use self::messages::{ReqClockSet, ResInitialize, ResInfo,
+pub use self::messages::{Message, MessageId, AckStatus, ReqClockSet, ResInitialize, ResInfo,
                          ResCalibration, ResPowerBuffer, ResPowerUse,
-                         ResClockInfo, DateTime, Pulses};
-use self::messages::{Message, MessageId, ReqHeader, ReqSwitch, ReqPowerBuffer};
+                         ResClockInfo, ResLinkedNode, ResPing, ResRemoveNode,
+                         RealTimeClock, DateTime, Pulses, AwakeReason, ProductType};
+use self::messages::{ReqHeader, ReqSwitch, ReqPowerBuffer, ReqLinkedNode,
+                     ReqJoinMode, ReqJoinAccept, ReqRemoveNode, ReqLogInterval,
+                     ReqRelayLock, ReqSchedule, ReqScheduleEnable,
+                     ReqSleepConfig, ReqSenseInterval, ReqScanConfig};
 use super::error;
 
 const HEADER: [u8; 4] = [5, 5, 3, 3];
@@ -14,24 +24,103 @@ const FOOTER: [u8; 2] = [13, 10];
 const EOM: u8 = 10;
 const CRC_SIZE: usize = 4;
 const DEFAULT_RETRIES: u8 = 3;
+// maximum number of unsolicited messages which are kept (the oldest are dropped)
+const MAX_UNSOLICITED: usize = 100;
+// maximum number of requests which are sent before their responses have been received
+const MAX_IN_FLIGHT: usize = 8;
+// sequence number of the acknowledge by which a Circle reports its relay has been switched with
+// its button (this sequence number is never assigned to requests)
+const SEQ_RELAY_SWITCHED: u16 = 0xFFFD;
+
+// maximum delay between two attempts to restore the link to the USB stick
+const MAX_RECONNECT_BACKOFF_SECS: u64 = 60;
+// maximum number of times the link to the USB stick is restored during a single request (or a
+// pass of pipelined requests or polling), so a link which is lost over and over again is given up
+const MAX_RECONNECTS: u8 = 3;
+
+// errors after which a request is simply repeated, i.e. no response in time or a node which did
+// not respond to the USB stick (other errors might indicate the link to the USB stick has been
+// lost, see `Protocol::reconnect`)
+fn is_timeout(e: &error::PlError) -> bool {
+    match *e {
+        error::PlError::Io(ref e) => e.kind() == io::ErrorKind::TimedOut,
+        error::PlError::Nack(AckStatus::Timeout, _) => true,
+        _ => false
+    }
+}
+
+/// Policy to restore the link to the USB stick when it has been lost (i.e. the USB stick has been
+/// unplugged). The port is opened again, the USB stick is initialized again and the calibration
+/// information of the known Circles is retrieved again, after which the failed request is
+/// repeated (regardless of the number of retries, which only applies to nodes which did not
+/// respond).
+///
+/// The link is restored by the request which noticed it has been lost. Requests of other threads
+/// wait until the link has been restored (or given up), including the delays between the attempts.
+#[derive(Debug, Copy, Clone)]
+pub struct Reconnect {
+    /// Number of attempts to restore the link (no attempts disables reconnecting)
+    pub attempts: u8,
+    /// Delay before the first attempt, which is doubled after each failed attempt (up to a minute)
+    pub backoff: Duration
+}
 
 /// Plugwise communication snooper setting.
 pub enum ProtocolSnoop<'a> {
     /// Log nothing (default).
     Nothing,
     /// Log developer readable data of the Plugwise communication.
-    Debug(&'a mut Write),
+    Debug(&'a mut (Write + Send)),
     /// Log the relevant raw serial communication of the Plugwise communication.
-    Raw(&'a mut Write),
+    Raw(&'a mut (Write + Send)),
     /// Log all raw serial communication of the Plugwise communication (very verbose, which
     /// actually doesn't make much sense, unless you're a developer of Plugwise devices).
-    All(&'a mut Write)
+    All(&'a mut (Write + Send))
+}
+
+/// Unsolicited event of the Plugwise network.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A unlinked node requested to join the network (see `Plugwise::enable_join`)
+    JoinRequest(u64),
+    /// A node has joined the network after its join request has been accepted (see
+    /// `Plugwise::accept_join`)
+    JoinAccepted(u64),
+    /// A sleeping device (Scan, Sense or Switch) announced to be awake
+    Awake(u64, AwakeReason),
+    /// A button of a Switch has been pressed, or a Scan detected motion (or the motion has been
+    /// reset): the address of the device, the group (button) and its new state
+    SwitchGroup(u64, u8, bool),
+    /// A Sense reported the temperature (in degrees Celsius) and relative humidity (in percent)
+    SenseReport(u64, f64, f64),
+    /// The relay of a Circle has been switched with its button: the address of the Circle and the
+    /// new state of the relay
+    RelaySwitched(u64, bool),
+    /// A command which has been queued for a sleeping device failed: the address of the device and
+    /// the status by which the device refused the command (the command is dropped), or `None` when
+    /// the device did not respond (the command is sent again when the device is awake again)
+    CommandFailed(u64, Option<AckStatus>),
+    /// A message which is not known (or could not be decoded): the message identifier and the raw
+    /// message (as received in hexadecimal ASCII representation without the CRC)
+    Unknown(u16, Vec<u8>),
 }
 
 pub struct Protocol<'a, R> {
     reader: io::BufReader<R>,
     snoop: ProtocolSnoop<'a>,
     retries: u8,
+    unsolicited: Vec<Message>,
+    // commands for sleeping devices which are sent when the device is awake
+    pending: BTreeMap<u64, Vec<(Message, AckStatus)>>,
+    // sleeping devices which are awake and have pending commands
+    awake: Vec<u64>,
+    subscribers: Vec<mpsc::Sender<Event>>,
+    // calibration information of the circles (retrieved again after reconnecting)
+    calibrations: BTreeMap<u64, ResCalibration>,
+    reconnect: Reconnect,
+    // opens the port again when the link to the USB stick has been lost
+    reopen: Option<Box<FnMut() -> error::PlResult<R> + Send + 'a>>,
+    reconnecting: bool,
 }
 
 impl<'a, R: Read + Write> Protocol<'a, R> {
@@ -41,6 +130,17 @@ impl<'a, R: Read + Write> Protocol<'a, R> {
             reader: io::BufReader::with_capacity(1000, port),
             snoop: ProtocolSnoop::Nothing,
             retries: DEFAULT_RETRIES,
+            unsolicited: vec![],
+            pending: BTreeMap::new(),
+            awake: vec![],
+            subscribers: vec![],
+            calibrations: BTreeMap::new(),
+            reconnect: Reconnect {
+                attempts: 0,
+                backoff: Duration::from_millis(0)
+            },
+            reopen: None,
+            reconnecting: false,
         }
     }
 
@@ -52,6 +152,65 @@ impl<'a, R: Read + Write> Protocol<'a, R> {
         self.snoop = snoop;
     }
 
+    /// Restore the link to the USB stick using the given function to open the port when the link
+    /// has been lost (see `Reconnect`)
+    pub fn set_reconnect<F>(&mut self, reconnect: Reconnect, reopen: F)
+        where F: FnMut() -> error::PlResult<R> + Send + 'a {
+        self.reconnect = reconnect;
+        self.reopen = Some(Box::new(reopen));
+    }
+
+    /// Try to restore the link to the USB stick when the given error indicates the link has been
+    /// lost (otherwise the error is returned)
+    fn reconnect(&mut self, cause: error::PlError) -> error::PlResult<()> {
+        let lost = match cause {
+            error::PlError::Serial(_) => true,
+            error::PlError::Io(ref e) => e.kind() != io::ErrorKind::TimedOut,
+            _ => false
+        };
+
+        if !lost || self.reconnecting || self.reopen.is_none() {
+            return Err(cause);
+        }
+
+        self.reconnecting = true;
+        let result = self.reconnect_helper(cause);
+        self.reconnecting = false;
+        result
+    }
+
+    fn reconnect_helper(&mut self, mut cause: error::PlError) -> error::PlResult<()> {
+        let max_backoff = Duration::from_secs(MAX_RECONNECT_BACKOFF_SECS);
+        let mut backoff = cmp::min(self.reconnect.backoff, max_backoff);
+
+        for attempt in 0..self.reconnect.attempts {
+            info!("link to USB stick lost ({}), reconnect attempt {}", cause, attempt + 1);
+            thread::sleep(backoff);
+            backoff = backoff.checked_mul(2).map_or(max_backoff, |b| cmp::min(b, max_backoff));
+
+            let port = match self.reopen {
+                Some(ref mut reopen) => reopen(),
+                None => return Err(cause)
+            };
+            match port {
+                Ok(port) => self.reader = io::BufReader::with_capacity(1000, port),
+                Err(e) => {
+                    cause = e;
+                    continue;
+                }
+            }
+
+            let macs = self.calibrations.keys().cloned().collect::<Vec<_>>();
+            let result = self.initialize().and_then(|_| self.calibrate_group(&macs));
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e) => cause = e
+            }
+        }
+
+        Err(cause)
+    }
+
     /// Send payload
     fn send_message_raw(&mut self, payload: &[u8]) -> error::PlResult<()> {
         let crc = format!("{:04X}", State::<XMODEM>::calculate(payload)).into_bytes();
@@ -148,37 +307,186 @@ impl<'a, R: Read + Write> Protocol<'a, R> {
         Ok(payload.cloned().collect())
     }
 
-    /// Keep receiving messages until the given message identifier has been received
-    fn expect_message(&mut self, expected_message_id: MessageId) -> error::PlResult<Message> {
-        loop {
-            let msg = try!(self.receive_message_raw());
-            let msg = try!(Message::from_payload(&msg));
+    /// Wait until a message has been received and decode it
+    fn receive_message(&mut self) -> error::PlResult<Message> {
+        let (raw, msg) = loop {
+            let raw = try!(self.receive_message_raw());
+            match Message::from_payload(&raw) {
+                Ok(msg) => break (raw, msg),
+                Err(e) => {
+                    // a (valid) message which cannot be decoded is reported to the subscribers
+                    // and skipped, so it does not abort the request which is waiting
+                    info!("skipping undecodable message ({}): {:?}", e, raw);
+                    let msgid = str::from_utf8(&raw[..cmp::min(4, raw.len())]).ok()
+                                    .and_then(|msgid| u16::from_str_radix(msgid, 16).ok())
+                                    .unwrap_or(0);
+                    self.notify(Event::Unknown(msgid, raw));
+                }
+            }
+        };
+
+        debug!("received: {:?}", msg);
+
+        if let ProtocolSnoop::Debug(ref mut writer) = self.snoop {
+            match msg {
+                Message::Unknown(header, ref payload) => {
+                    // show the payload as received to ease the analysis of unknown messages
+                    try!(writer.write_fmt(format_args!("< Unknown(msgid: {:04X}, count: {:04X}, \
+                                                        mac: {:016X}, payload: {})\n",
+                                                       header.msgid.to_u16(), header.count,
+                                                       header.mac,
+                                                       String::from_utf8_lossy(payload))));
+                },
+                _ => try!(writer.write_fmt(format_args!("< {:?}\n", msg)))
+            }
+        }
+
+        if let Message::Unknown(header, _) = msg {
+            self.notify(Event::Unknown(header.msgid.to_u16(), raw));
+        }
 
-            debug!("received: {:?}", msg);
+        Ok(msg)
+    }
+
+    /// Deliver a event to all subscribers (subscribers which are gone are forgotten)
+    fn notify(&mut self, event: Event) {
+        self.subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
 
-            if let ProtocolSnoop::Debug(ref mut writer) = self.snoop {
-                try!(writer.write_fmt(format_args!("< {:?}\n", msg)));
+    /// Subscribe to the unsolicited events
+    pub fn subscribe(&mut self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Keep unsolicited messages for later retrieval (other messages are dropped)
+    fn keep_unsolicited(&mut self, msg: Message) {
+        match msg {
+            Message::ResJoinRequest(header) =>
+                self.notify(Event::JoinRequest(header.mac)),
+            Message::ResAwake(header, res) =>
+                self.notify(Event::Awake(header.mac, res.reason)),
+            Message::ResSwitchGroup(header, res) =>
+                self.notify(Event::SwitchGroup(header.mac, res.group, res.on)),
+            Message::ResSenseReport(header, res) =>
+                self.notify(Event::SenseReport(header.mac, res.temperature, res.humidity)),
+            Message::Ack(_, ack) if ack.status == AckStatus::JoinAccepted => {
+                if let Some(mac) = ack.mac {
+                    self.notify(Event::JoinAccepted(mac));
+                }
+            },
+            Message::Ack(header, ack) if header.count == SEQ_RELAY_SWITCHED => {
+                match (ack.mac, ack.status) {
+                    (Some(mac), AckStatus::RelaySwitchedOn) =>
+                        self.notify(Event::RelaySwitched(mac, true)),
+                    (Some(mac), AckStatus::RelaySwitchedOff) =>
+                        self.notify(Event::RelaySwitched(mac, false)),
+                    _ => {}
+                }
+            },
+            _ => {}
+        }
+
+        if let Message::ResAwake(header, _) = msg {
+            if self.pending.contains_key(&header.mac) && !self.awake.contains(&header.mac) {
+                self.awake.push(header.mac);
             }
+        }
 
-            if msg.to_message_id() == expected_message_id {
-                return Ok(msg)
+        if msg.to_message_id().is_unsolicited() {
+            if self.unsolicited.len() == MAX_UNSOLICITED {
+                let _ = self.unsolicited.remove(0);
             }
+            self.unsolicited.push(msg);
+        } else if let Message::Unknown(..) = msg {
+            // already reported to the subscribers
+        } else if let Message::Ack(header, ack) = msg {
+            if header.count != SEQ_RELAY_SWITCHED && ack.status != AckStatus::JoinAccepted {
+                info!("dropping stale message {:?}", msg);
+            }
+            // otherwise already reported to the subscribers
+        } else {
+            info!("dropping stale message {:?}", msg);
         }
     }
 
-    fn wait_for_mac_ack(&mut self, expected_mac: u64) -> error::PlResult<()> {
+    /// Wait until the USB stick accepted a request, and return the sequence number which the USB
+    /// stick assigned to the request
+    fn wait_for_request_ack(&mut self, expected_mac: Option<u64>) -> error::PlResult<u16> {
         loop {
-            let ack = try!(self.expect_message(MessageId::Ack));
-            if let Message::Ack(_, ack) = ack {
-                if let Some(ack_mac) = ack.mac {
-                    if ack_mac == expected_mac {
-                        break;
+            let msg = try!(self.receive_message());
+
+            if let Message::Ack(header, ack) = msg {
+                if ack.mac.is_none() {
+                    match ack.status {
+                        AckStatus::Success => return Ok(header.count),
+                        // USB stick refused to handle the request
+                        AckStatus::Nack => return Err(error::PlError::Nack(ack.status,
+                                                                           expected_mac)),
+                        _ => {}
                     }
                 }
             }
+
+            self.keep_unsolicited(msg);
         }
+    }
 
-        Ok(())
+    /// Keep receiving messages until the response (with the given message identifier) of the
+    /// request with the given sequence number has been received
+    fn expect_response(&mut self,
+                       seq: u16,
+                       expected_mac: Option<u64>,
+                       expected_message_id: MessageId) -> error::PlResult<Message> {
+        loop {
+            let msg = try!(self.receive_message());
+
+            if let Some(header) = msg.get_header() {
+                if header.count == seq {
+                    if msg.to_message_id() == expected_message_id &&
+                       expected_mac.map_or(true, |mac| mac == header.mac) {
+                        return Ok(msg)
+                    }
+                    if let Message::Ack(_, ack) = msg {
+                        if ack.mac.is_none() && ack.status.is_nack() {
+                            // USB stick reports the request failed (i.e. no response of the node)
+                            return Err(error::PlError::Nack(ack.status, expected_mac));
+                        }
+                    }
+                }
+            }
+
+            self.keep_unsolicited(msg);
+        }
+    }
+
+    /// Wait for the acknowledge of a Circle of the request with the given sequence number and
+    /// check whether it reports the expected status (a timeout acknowledge, of a Circle which did
+    /// not respond, is repeated by the caller, see `is_timeout`)
+    fn wait_for_mac_ack(&mut self,
+                        seq: u16,
+                        expected_mac: u64,
+                        expected_status: AckStatus) -> error::PlResult<()> {
+        loop {
+            let msg = try!(self.receive_message());
+
+            if let Message::Ack(header, ack) = msg {
+                if header.count == seq {
+                    if ack.mac == Some(expected_mac) {
+                        if ack.status != expected_status {
+                            return Err(error::PlError::Nack(ack.status, ack.mac));
+                        }
+                        return Ok(());
+                    } else if ack.mac.is_none() && ack.status.is_nack() {
+                        // USB stick reports the request failed (i.e. no response of the node)
+                        return Err(error::PlError::Nack(ack.status, Some(expected_mac)));
+                    }
+                }
+            }
+
+            self.keep_unsolicited(msg);
+        }
     }
 
     /// Send message
@@ -191,60 +499,388 @@ impl<'a, R: Read + Write> Protocol<'a, R> {
         Ok(())
     }
 
+    /// Send a request and wait until it has been accepted by the USB stick (see
+    /// `wait_for_request_ack`)
+    fn send_request(&mut self, message: &Message) -> error::PlResult<u16> {
+        try!(self.send_message(message));
+        self.wait_for_request_ack(message.get_destination_mac())
+    }
+
     /// Send a message and wait for response
     fn send_and_expect(&mut self, message: Message, expected: MessageId) -> error::PlResult<Message> {
+        let result = self.send_and_expect_helper(message, expected);
+        self.send_pending();
+        result
+    }
+
+    fn send_and_expect_helper(&mut self,
+                              message: Message,
+                              expected: MessageId) -> error::PlResult<Message> {
         let mut retries = self.retries;
+        let mut reconnects = MAX_RECONNECTS;
+        let mac = message.get_destination_mac();
 
         loop {
-            try!(self.send_message(&message));
-            match self.expect_message(expected) {
+            let result = match self.send_request(&message) {
+                Ok(seq) => self.expect_response(seq, mac, expected),
+                Err(e) => Err(e)
+            };
+            match result {
                 Ok(n) => return Ok(n),
                 Err(e) => {
-                    if retries == 0 {
-                        return Err(e);
-                    } else if let error::PlError::Io(e) = e {
-                        if e.kind() != io::ErrorKind::TimedOut {
-                            return Err(error::PlError::Io(e));
-                        } else {
-                            retries -= 1;
+                    if !is_timeout(&e) {
+                        if reconnects == 0 {
+                            return Err(e);
                         }
-                    } else {
+                        reconnects -= 1;
+                        // the request is repeated when the link to the USB stick is restored
+                        try!(self.reconnect(e));
+                    } else if retries == 0 {
                         return Err(e);
+                    } else {
+                        retries -= 1;
                     }
                 }
             }
         }
     }
 
-    /// Send a message and wait for acknowledge with a mac
-    fn send_and_expect_ack(&mut self, message: Message, mac: u64) -> error::PlResult<()> {
+    /// Send a message and wait for acknowledge with a mac (or only for the acknowledge of the USB
+    /// stick itself when no mac is given)
+    fn send_and_expect_ack(&mut self,
+                           message: Message,
+                           mac: Option<u64>,
+                           status: AckStatus) -> error::PlResult<()> {
+        let result = self.send_and_expect_ack_helper(message, mac, status);
+        self.send_pending();
+        result
+    }
+
+    fn send_and_expect_ack_helper(&mut self,
+                                  message: Message,
+                                  mac: Option<u64>,
+                                  status: AckStatus) -> error::PlResult<()> {
         let mut retries = self.retries;
+        let mut reconnects = MAX_RECONNECTS;
 
         loop {
-            try!(self.send_message(&message));
             debug!("sending {:?}", message);
-            match self.wait_for_mac_ack(mac) {
+            let result = match (self.send_request(&message), mac) {
+                (Ok(seq), Some(mac)) => self.wait_for_mac_ack(seq, mac, status),
+                (Ok(_), None) => Ok(()),
+                (Err(e), _) => Err(e)
+            };
+            match result {
                 Ok(n) => {
                     return Ok(n)
                 }
                 Err(e) => {
-                    if retries == 0 {
+                    if !is_timeout(&e) {
+                        if reconnects == 0 {
+                            return Err(e);
+                        }
+                        reconnects -= 1;
+                        // the request is repeated when the link to the USB stick is restored
+                        try!(self.reconnect(e));
+                    } else if retries == 0 {
                         return Err(e);
-                    } else if let error::PlError::Io(e) = e {
-                        if e.kind() != io::ErrorKind::TimedOut {
-                            return Err(error::PlError::Io(e));
+                    } else {
+                        retries -= 1;
+                    }
+                    info!("retries pending {} for {:?}", retries, message);
+                }
+            }
+        }
+    }
+
+    /// Send a request for each of the given keys (i.e. the addresses of circles) without waiting
+    /// for the responses of the earlier requests (at most `MAX_IN_FLIGHT` requests are outstanding
+    /// at once), and collect the responses as they are received. The `response` closure is given
+    /// each message of which the sequence number matches the request of a key, and returns the
+    /// result of the key when the message is its response (or acknowledge). Requests of
+    /// unresponsive circles are repeated afterwards, the result of each key is reported
+    /// separately.
+    fn pipeline<K, T, F, G>(&mut self,
+                            keys: &[K],
+                            request: F,
+                            mut response: G) -> error::PlResult<BTreeMap<K, error::PlResult<T>>>
+        where K: Ord + Copy,
+              F: Fn(K) -> Message,
+              G: FnMut(K, &Message) -> Option<error::PlResult<T>> {
+        let mut pending = keys.iter().cloned().collect::<BTreeSet<K>>();
+        let mut result = BTreeMap::new();
+        let mut retries = self.retries;
+        // number of times the USB stick reported the circle of a key did not respond
+        let mut timeouts = BTreeMap::new();
+        let mut reconnects = MAX_RECONNECTS;
+
+        loop {
+            let mut waiting = pending.iter().cloned().collect::<VecDeque<K>>();
+            // the USB stick accepts the requests in order of sending
+            let mut unaccepted = VecDeque::new();
+            let mut sequence = BTreeMap::new();
+
+            while !waiting.is_empty() || !unaccepted.is_empty() || !sequence.is_empty() {
+                while unaccepted.len() + sequence.len() < MAX_IN_FLIGHT {
+                    match waiting.pop_front() {
+                        Some(key) => {
+                            match self.send_message(&request(key)) {
+                                Ok(_) => unaccepted.push_back(key),
+                                Err(e) => {
+                                    if reconnects == 0 {
+                                        return Err(e);
+                                    }
+                                    reconnects -= 1;
+                                    // the outstanding requests are lost when the link to the USB
+                                    // stick is restored
+                                    try!(self.reconnect(e));
+                                    unaccepted.clear();
+                                    sequence.clear();
+                                    waiting.push_front(key);
+                                }
+                            }
+                        },
+                        None => break
+                    }
+                }
+
+                let msg = match self.receive_message() {
+                    Ok(msg) => msg,
+                    Err(error::PlError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut => {
+                        // the outstanding requests are lost, the requests which are not sent yet
+                        // are still sent in this pass
+                        unaccepted.clear();
+                        sequence.clear();
+                        continue;
+                    },
+                    Err(e) => {
+                        if reconnects == 0 {
+                            return Err(e);
+                        }
+                        reconnects -= 1;
+                        try!(self.reconnect(e));
+                        unaccepted.clear();
+                        sequence.clear();
+                        continue;
+                    }
+                };
+
+                let count = match msg.get_header() {
+                    Some(header) => header.count,
+                    None => {
+                        self.keep_unsolicited(msg);
+                        continue;
+                    }
+                };
+
+                if let Message::Ack(_, ack) = msg {
+                    let timed_out = match sequence.get(&count) {
+                        Some(&key) if ack.status == AckStatus::Timeout &&
+                                      ack.mac.map_or(true, |ack_mac| {
+                                          Some(ack_mac) == request(key).get_destination_mac()
+                                      }) => Some(key),
+                        _ => None
+                    };
+                    if let Some(key) = timed_out {
+                        // the request of a circle which did not respond is repeated (as long as
+                        // its retries last)
+                        sequence.remove(&count);
+                        let n = timeouts.entry(key).or_insert(0);
+                        if *n < self.retries {
+                            *n += 1;
+                            waiting.push_back(key);
                         } else {
-                            retries -= 1;
+                            pending.remove(&key);
+                            let mac = request(key).get_destination_mac();
+                            result.insert(key, Err(error::PlError::Nack(ack.status, mac)));
                         }
-                    } else {
+                        continue;
+                    }
+                    if ack.mac.is_none() {
+                        let failed = match ack.status {
+                            AckStatus::Success => {
+                                if let Some(key) = unaccepted.pop_front() {
+                                    sequence.insert(count, key);
+                                }
+                                None
+                            },
+                            // USB stick refused to handle the request
+                            AckStatus::Nack => unaccepted.pop_front(),
+                            // USB stick reports the request failed
+                            status if status.is_nack() => sequence.remove(&count),
+                            _ => None
+                        };
+                        if let Some(key) = failed {
+                            pending.remove(&key);
+                            let mac = request(key).get_destination_mac();
+                            result.insert(key, Err(error::PlError::Nack(ack.status, mac)));
+                        }
+                        continue;
+                    }
+                }
+
+                let res = match sequence.get(&count) {
+                    Some(&key) => response(key, &msg).map(|res| (key, res)),
+                    None => None
+                };
+                match res {
+                    Some((key, res)) => {
+                        sequence.remove(&count);
+                        pending.remove(&key);
+                        result.insert(key, res);
+                    },
+                    None => self.keep_unsolicited(msg)
+                }
+            }
+
+            if pending.is_empty() {
+                break;
+            } else if retries == 0 {
+                for key in pending {
+                    result.insert(key, Err(error::PlError::Io(
+                        io::Error::new(io::ErrorKind::TimedOut, "no response received"))));
+                }
+                break;
+            }
+
+            retries -= 1;
+            info!("retries pending {} for {} requests", retries, pending.len());
+        }
+
+        self.send_pending();
+
+        Ok(result)
+    }
+
+    /// Send the pending commands of the sleeping devices which are awake. This is done as soon as
+    /// the exchange in which the awake announcement has been received has finished (the commands
+    /// cannot be sent while waiting for a response). Failed commands are reported to the
+    /// subscribers of the device, see `Event::CommandFailed`.
+    fn send_pending(&mut self) {
+        while let Some(mac) = self.awake.pop() {
+            let mut commands = self.pending.remove(&mac).unwrap_or(vec![]);
+
+            while !commands.is_empty() {
+                let (message, status) = commands[0].clone();
+
+                match self.send_and_expect_ack(message, Some(mac), status) {
+                    Ok(_) => {
+                        let _ = commands.remove(0);
+                    },
+                    Err(e) => {
+                        info!("command for sleeping device {:016X} failed: {}", mac, e);
+                        let refused = match e {
+                            error::PlError::Nack(status, _) if status != AckStatus::Timeout =>
+                                Some(status),
+                            _ => None
+                        };
+                        if refused.is_some() {
+                            // the device refused the command; do not offer it again
+                            let _ = commands.remove(0);
+                        }
+                        if !commands.is_empty() {
+                            // retry the remaining commands when the device is awake again
+                            self.pending.insert(mac, commands);
+                        }
+                        self.notify(Event::CommandFailed(mac, refused));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Queue a command for a sleeping device (it is sent when the device is awake)
+    fn queue_command(&mut self, mac: u64, message: Message, status: AckStatus) {
+        self.pending.entry(mac).or_insert(vec![]).push((message, status));
+    }
+
+    /// Number of commands which are waiting for a sleeping device to be awake
+    pub fn pending_commands(&self, mac: u64) -> usize {
+        self.pending.get(&mac).map_or(0, |commands| commands.len())
+    }
+
+    /// Receive all pending messages until nothing is received anymore (i.e. a read timeout).
+    /// Pending commands of sleeping devices are sent as soon as the device is awake.
+    pub fn poll(&mut self) -> error::PlResult<()> {
+        let mut reconnects = MAX_RECONNECTS;
+
+        loop {
+            self.send_pending();
+
+            match self.receive_message() {
+                Ok(msg) => self.keep_unsolicited(msg),
+                Err(error::PlError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut => {
+                    return Ok(())
+                },
+                Err(e) => {
+                    if reconnects == 0 {
                         return Err(e);
                     }
-                    info!("retries pending {} for {:?}", retries, message);
+                    reconnects -= 1;
+                    try!(self.reconnect(e));
                 }
             }
         }
     }
 
+    /// Retrieve (and forget) all received unsolicited messages of a given kind
+    pub fn take_unsolicited(&mut self, message_id: MessageId) -> Vec<Message> {
+        let (taken, kept) = self.unsolicited.drain(..)
+                                            .partition(|msg| msg.to_message_id() == message_id);
+        self.unsolicited = kept;
+        taken
+    }
+
+    /// Retrieve (and forget) all received unsolicited messages of a given device
+    pub fn take_unsolicited_from(&mut self, mac: u64) -> Vec<Message> {
+        let (taken, kept) = self.unsolicited.drain(..)
+                                            .partition(|msg| msg.get_source_mac() == Some(mac));
+        self.unsolicited = kept;
+        taken
+    }
+
+    /// Queue the sleep configuration of a sleeping device (durations in seconds for `stay_active`
+    /// and in minutes for the others)
+    pub fn queue_sleep_config(&mut self,
+                              mac: u64,
+                              stay_active: u8,
+                              sleep_for: u16,
+                              maintenance_interval: u16) {
+        self.queue_command(mac,
+                           Message::ReqSleepConfig(ReqHeader{mac: mac},
+                                                   ReqSleepConfig {
+                                                       stay_active: stay_active,
+                                                       maintenance_interval: maintenance_interval,
+                                                       sleep_for: sleep_for
+                                                   }),
+                           AckStatus::SleepConfigAccepted);
+    }
+
+    /// Queue the report interval (in minutes) of a Sense
+    pub fn queue_sense_interval(&mut self, mac: u64, interval: u8) {
+        self.queue_command(mac,
+                           Message::ReqSenseInterval(ReqHeader{mac: mac},
+                                                     ReqSenseInterval{interval: interval}),
+                           AckStatus::SenseIntervalAccepted);
+    }
+
+    /// Queue the motion detection configuration of a Scan
+    pub fn queue_scan_config(&mut self,
+                             mac: u64,
+                             sensitivity: u8,
+                             reset_timer: u8,
+                             daylight: bool) {
+        self.queue_command(mac,
+                           Message::ReqScanConfig(ReqHeader{mac: mac},
+                                                  ReqScanConfig {
+                                                      reset_timer: reset_timer,
+                                                      sensitivity: sensitivity,
+                                                      daylight: daylight
+                                                  }),
+                           AckStatus::ScanConfigAccepted);
+    }
+
     /// Initialize the Plugwise USB stick
     pub fn initialize(&mut self) -> error::PlResult<ResInitialize> {
         let msg = try!(self.send_and_expect(Message::ReqInitialize,
@@ -267,11 +903,154 @@ impl<'a, R: Read + Write> Protocol<'a, R> {
         }
     }
 
+    /// Get an entry of the node table of the Circle+
+    pub fn get_linked_node(&mut self, mac: u64, index: u8) -> error::PlResult<ResLinkedNode> {
+        let msg = try!(self.send_and_expect(Message::ReqLinkedNode(ReqHeader{mac: mac},
+                                                                   ReqLinkedNode{index: index}),
+                                            MessageId::ResLinkedNode));
+
+        match msg {
+            Message::ResLinkedNode(_, res) => Ok(res),
+            _ => Err(error::PlError::UnexpectedResponse)
+        }
+    }
+
+    /// Get a range of entries of the node table of the Circle+ (see `pipeline`)
+    pub fn get_linked_node_group(&mut self, mac: u64, indices: &[u8])
+                                 -> error::PlResult<BTreeMap<u8, error::PlResult<ResLinkedNode>>> {
+        self.pipeline(indices,
+                      |index| Message::ReqLinkedNode(ReqHeader{mac: mac},
+                                                     ReqLinkedNode{index: index}),
+                      |index, msg| match *msg {
+                          Message::ResLinkedNode(header, res) if header.mac == mac &&
+                                                                 res.index == index =>
+                              Some(Ok(res)),
+                          _ => None
+                      })
+    }
+
+    /// Ping a circle
+    pub fn ping(&mut self, mac: u64) -> error::PlResult<ResPing> {
+        let msg = try!(self.send_and_expect(Message::ReqPing(ReqHeader{mac: mac}),
+                                            MessageId::ResPing));
+
+        match msg {
+            Message::ResPing(_, res) => Ok(res),
+            _ => Err(error::PlError::UnexpectedResponse)
+        }
+    }
+
+    /// Allow or disallow unlinked nodes to request to join the network
+    pub fn set_join_mode(&mut self, enable: bool) -> error::PlResult<()> {
+        try!(self.send_and_expect_ack(Message::ReqJoinMode(ReqJoinMode{enable: enable}),
+                                      None,
+                                      AckStatus::Success));
+        Ok(())
+    }
+
+    /// Retrieve the addresses of the nodes which requested to join the network (the pending
+    /// messages are received first, see `poll`)
+    pub fn get_join_requests(&mut self) -> error::PlResult<Vec<u64>> {
+        try!(self.poll());
+
+        let mut result = vec![];
+        for msg in self.take_unsolicited(MessageId::ResJoinRequest) {
+            if let Message::ResJoinRequest(header) = msg {
+                if !result.contains(&header.mac) {
+                    result.push(header.mac);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Accept or reject a node to join the network
+    pub fn accept_join(&mut self, mac: u64, accept: bool) -> error::PlResult<()> {
+        try!(self.send_and_expect_ack(Message::ReqJoinAccept(ReqJoinAccept{mac: mac,
+                                                                           accept: accept}),
+                                      None,
+                                      AckStatus::Success));
+        Ok(())
+    }
+
+    /// Remove a node from the network of the Circle+
+    pub fn remove_node(&mut self, mac: u64, node: u64) -> error::PlResult<ResRemoveNode> {
+        let msg = try!(self.send_and_expect(Message::ReqRemoveNode(ReqHeader{mac: mac},
+                                                                   ReqRemoveNode{mac: node}),
+                                            MessageId::ResRemoveNode));
+
+        match msg {
+            Message::ResRemoveNode(_, res) => Ok(res),
+            _ => Err(error::PlError::UnexpectedResponse)
+        }
+    }
+
     /// Switch a circle
     pub fn switch(&mut self, mac: u64, on: bool) -> error::PlResult<()> {
         try!(self.send_and_expect_ack(Message::ReqSwitch(ReqHeader{mac: mac},
                                                          ReqSwitch{on: on}),
-                                      mac));
+                                      Some(mac),
+                                      if on {
+                                          AckStatus::RelaySwitchedOn
+                                      } else {
+                                          AckStatus::RelaySwitchedOff
+                                      }));
+        Ok(())
+    }
+
+    /// Switch a group of circles. The requests are pipelined (requests of unresponsive circles
+    /// are repeated afterwards), the result of each circle is reported separately.
+    pub fn switch_group(&mut self,
+                        macs: &[u64],
+                        on: bool) -> error::PlResult<BTreeMap<u64, error::PlResult<()>>> {
+        let expected_status = if on {
+            AckStatus::RelaySwitchedOn
+        } else {
+            AckStatus::RelaySwitchedOff
+        };
+
+        self.pipeline(macs,
+                      |mac| Message::ReqSwitch(ReqHeader{mac: mac}, ReqSwitch{on: on}),
+                      |mac, msg| match *msg {
+                          Message::Ack(_, ack) if ack.mac == Some(mac) => {
+                              Some(if ack.status == expected_status {
+                                  Ok(())
+                              } else {
+                                  Err(error::PlError::Nack(ack.status, ack.mac))
+                              })
+                          },
+                          _ => None
+                      })
+    }
+
+    /// Lock (or unlock) the relay of a circle
+    pub fn set_relay_lock(&mut self, mac: u64, lock: bool) -> error::PlResult<()> {
+        try!(self.send_and_expect_ack(Message::ReqRelayLock(ReqHeader{mac: mac},
+                                                            ReqRelayLock{lock: lock}),
+                                      Some(mac),
+                                      AckStatus::RelayLockAccepted));
+        Ok(())
+    }
+
+    /// Upload a part (four slots starting at slot `4 * index`) of the switching schedule
+    pub fn set_schedule(&mut self, mac: u64, index: u32, slots: [bool; 4]) -> error::PlResult<()> {
+        try!(self.send_and_expect_ack(Message::ReqSchedule(ReqHeader{mac: mac},
+                                                           ReqSchedule {
+                                                               index: index,
+                                                               slots: slots
+                                                           }),
+                                      Some(mac),
+                                      AckStatus::ScheduleAccepted));
+        Ok(())
+    }
+
+    /// Enable (or disable) the switching schedule of a circle
+    pub fn enable_schedule(&mut self, mac: u64, enable: bool) -> error::PlResult<()> {
+        try!(self.send_and_expect_ack(Message::ReqScheduleEnable(ReqHeader{mac: mac},
+                                                                 ReqScheduleEnable{enable: enable}),
+                                      Some(mac),
+                                      AckStatus::ScheduleAccepted));
         Ok(())
     }
 
@@ -281,23 +1060,74 @@ impl<'a, R: Read + Write> Protocol<'a, R> {
                                             MessageId::ResCalibration));
 
         match msg {
-            Message::ResCalibration(_, res) => Ok(res),
+            Message::ResCalibration(_, res) => {
+                self.calibrations.insert(mac, res);
+                Ok(res)
+            },
             _ => Err(error::PlError::UnexpectedResponse)
         }
     }
 
-    /// Retrieve power buffer
-    pub fn get_power_buffer(&mut self, mac: u64, addr: u32) -> error::PlResult<ResPowerBuffer> {
+    /// Retrieve calibration information of a group of circles (see `pipeline`)
+    pub fn calibrate_group(&mut self, macs: &[u64])
+                           -> error::PlResult<BTreeMap<u64, error::PlResult<ResCalibration>>> {
+        let result = try!(self.pipeline(macs,
+                                        |mac| Message::ReqCalibration(ReqHeader{mac: mac}),
+                                        |mac, msg| match *msg {
+                                            Message::ResCalibration(header, res)
+                                                if header.mac == mac => Some(Ok(res)),
+                                            _ => None
+                                        }));
+
+        for (&mac, res) in &result {
+            if let Ok(res) = *res {
+                self.calibrations.insert(mac, res);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Calibration information of a circle which has been retrieved before (see `calibrate`)
+    pub fn get_calibration(&self, mac: u64) -> Option<ResCalibration> {
+        self.calibrations.get(&mac).cloned()
+    }
+
+    /// Retrieve power buffer (of which each element covers the interval in seconds of either
+    /// consumed or produced power)
+    pub fn get_power_buffer(&mut self,
+                            mac: u64,
+                            addr: u32,
+                            consumption_interval: u32,
+                            production_interval: u32) -> error::PlResult<ResPowerBuffer> {
         let msg = try!(self.send_and_expect(Message::ReqPowerBuffer(ReqHeader{mac: mac},
                                                                     ReqPowerBuffer{logaddr: addr}),
                                             MessageId::ResPowerBuffer));
 
         match msg {
-            Message::ResPowerBuffer(_, res) => Ok(res),
+            Message::ResPowerBuffer(_, mut res) => {
+                res.set_interval(consumption_interval, production_interval);
+                Ok(res)
+            },
             _ => Err(error::PlError::UnexpectedResponse)
         }
     }
 
+    /// Set the interval (in minutes) of the power usage logging
+    pub fn set_log_interval(&mut self,
+                            mac: u64,
+                            consumption: u16,
+                            production: u16) -> error::PlResult<()> {
+        try!(self.send_and_expect_ack(Message::ReqLogInterval(ReqHeader{mac: mac},
+                                                              ReqLogInterval {
+                                                                  consumption: consumption,
+                                                                  production: production
+                                                              }),
+                                      Some(mac),
+                                      AckStatus::LogIntervalAccepted));
+        Ok(())
+    }
+
     /// Retrieve actual power usage
     pub fn get_power_usage(&mut self, mac: u64) -> error::PlResult<ResPowerUse> {
         let msg = try!(self.send_and_expect(Message::ReqPowerUse(ReqHeader{mac: mac}),
@@ -309,6 +1139,17 @@ impl<'a, R: Read + Write> Protocol<'a, R> {
         }
     }
 
+    /// Retrieve actual power usage of a group of circles (see `pipeline`)
+    pub fn get_power_usage_group(&mut self, macs: &[u64])
+                                 -> error::PlResult<BTreeMap<u64, error::PlResult<ResPowerUse>>> {
+        self.pipeline(macs,
+                      |mac| Message::ReqPowerUse(ReqHeader{mac: mac}),
+                      |mac, msg| match *msg {
+                          Message::ResPowerUse(header, res) if header.mac == mac => Some(Ok(res)),
+                          _ => None
+                      })
+    }
+
     /// Retrieve actual power usage
     pub fn get_clock_info(&mut self, mac: u64) -> error::PlResult<ResClockInfo> {
         let msg = try!(self.send_and_expect(Message::ReqClockInfo(ReqHeader{mac: mac}),
@@ -320,11 +1161,31 @@ impl<'a, R: Read + Write> Protocol<'a, R> {
         }
     }
 
+    /// Retrieve the real-time clock of the Circle+
+    pub fn get_realtime_clock(&mut self, mac: u64) -> error::PlResult<RealTimeClock> {
+        let msg = try!(self.send_and_expect(Message::ReqRealTimeClock(ReqHeader{mac: mac}),
+                                            MessageId::ResRealTimeClock));
+
+        match msg {
+            Message::ResRealTimeClock(_, res) => Ok(res),
+            _ => Err(error::PlError::UnexpectedResponse)
+        }
+    }
+
+    /// Set the real-time clock of the Circle+
+    pub fn set_realtime_clock(&mut self, mac: u64, clock: RealTimeClock) -> error::PlResult<()> {
+        try!(self.send_and_expect_ack(Message::ReqRealTimeClockSet(ReqHeader{mac: mac}, clock),
+                                      Some(mac),
+                                      AckStatus::RealTimeClockAccepted));
+        Ok(())
+    }
+
     /// Set clock
     pub fn set_clock(&mut self, mac: u64, clock_set: ReqClockSet) -> error::PlResult<()> {
         try!(self.send_and_expect_ack(Message::ReqClockSet(ReqHeader{mac: mac},
                                                            clock_set),
-                                      mac));
+                                      Some(mac),
+                                      AckStatus::ClockAccepted));
         Ok(())
     }
 }
@@ -335,15 +1196,108 @@ mod tests {
     // errors and panics when something strange happens.
 
     use super::super::stub;
+    use super::super::error;
     use super::*;
+    use std::io;
     use time;
 
+    /// Port which ignores all written data and only replays the given responses
+    struct Canned {
+        output: io::Cursor<Vec<u8>>
+    }
+
+    impl Canned {
+        fn new(responses: &[&[u8]]) -> Canned {
+            let mut output = vec![];
+            for response in responses {
+                let crc = format!("{:04X}", State::<XMODEM>::calculate(response)).into_bytes();
+                output.extend(HEADER.iter().cloned());
+                output.extend(response.iter().cloned());
+                output.extend(crc);
+                output.extend(FOOTER.iter().cloned());
+            }
+            Canned {
+                output: io::Cursor::new(output)
+            }
+        }
+    }
+
+    impl io::Read for Canned {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.output.read(buf) {
+                // behave like a serial port of which the read timeout expired
+                Ok(0) => Err(io::Error::new(io::ErrorKind::TimedOut, "no response pending")),
+                result => result
+            }
+        }
+    }
+
+    impl io::Write for Canned {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Port of the stub which disappears (like an unplugged USB stick) after the given number of
+    /// requests
+    struct Unplugged {
+        stub: stub::Stub,
+        requests: usize
+    }
+
+    impl io::Read for Unplugged {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.stub.read(buf)
+        }
+    }
+
+    impl io::Write for Unplugged {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.requests == 0 {
+                // like the serial port of a disconnected USB stick
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "device disconnected"));
+            }
+            self.requests -= buf.iter().filter(|&&b| b == b'\r').count();
+            self.stub.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
     #[test]
     fn stub_initialize() {
         let port = stub::Stub::new();
         let mut protocol = Protocol::new(port);
 
-        assert_eq!(true, protocol.initialize().unwrap().is_online);
+        let res = protocol.initialize().unwrap();
+        assert_eq!(true, res.is_online);
+
+        // the USB stick reports its own firmware as well
+        let _ = protocol.get_info(res.mac).unwrap();
+    }
+
+    #[test]
+    fn stub_get_linked_node() {
+        let mac = 0x0123456789abcdef;
+        let port = stub::Stub::new();
+        let mut protocol = Protocol::new(port);
+
+        let circle_plus = protocol.initialize().unwrap().network_id;
+        let _ = protocol.calibrate(mac).unwrap();
+
+        let node = protocol.get_linked_node(circle_plus, 0).unwrap();
+        assert_eq!(Some(mac), node.mac);
+        assert_eq!(0, node.index);
+
+        let node = protocol.get_linked_node(circle_plus, 1).unwrap();
+        assert_eq!(None, node.mac);
+        assert_eq!(1, node.index);
     }
 
     #[test]
@@ -385,6 +1339,344 @@ mod tests {
         assert_eq!(true, info2.relay_state);
     }
 
+    #[test]
+    fn canned_info_clock_not_set() {
+        let mac = 0x0123456789abcdef;
+        let port = Canned::new(&[b"0000000100C1",
+                                 b"002400010123456789ABCDEF0F0000000004839800856539\
+                                   07014023\
+                                   4E0844C202"]);
+        let mut protocol = Protocol::new(port);
+
+        // a month of 0 means the clock of the Circle has not been set
+        let info = protocol.get_info(mac).unwrap();
+        assert!(info.datetime.to_tm().is_none());
+    }
+
+    #[test]
+    fn stub_ping() {
+        let mac = 0x0123456789abcdef;
+        let port = stub::Stub::new();
+        let mut protocol = Protocol::new(port);
+
+        let _ = protocol.ping(mac).unwrap();
+    }
+
+    #[test]
+    fn stub_join() {
+        let port = stub::Stub::new();
+        let mut protocol = Protocol::new(port);
+
+        let circle_plus = protocol.initialize().unwrap().network_id;
+        assert_eq!(None, protocol.get_linked_node(circle_plus, 0).unwrap().mac);
+        assert!(protocol.get_join_requests().unwrap().is_empty());
+
+        protocol.set_join_mode(true).unwrap();
+        let requests = protocol.get_join_requests().unwrap();
+        assert_eq!(1, requests.len());
+        assert!(protocol.get_join_requests().unwrap().is_empty());
+
+        protocol.accept_join(requests[0], true).unwrap();
+        protocol.set_join_mode(false).unwrap();
+        assert_eq!(Some(requests[0]), protocol.get_linked_node(circle_plus, 0).unwrap().mac);
+    }
+
+    #[test]
+    fn stub_join_event() {
+        let port = stub::Stub::new();
+        let mut protocol = Protocol::new(port);
+        let events = protocol.subscribe();
+
+        protocol.set_join_mode(true).unwrap();
+        // the join request is received while waiting for the response of another request
+        let _ = protocol.initialize().unwrap();
+
+        match events.try_recv() {
+            Ok(Event::JoinRequest(_)) => {},
+            other => panic!("unexpected event: {:?}", other)
+        }
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn canned_events() {
+        let mac = 0x000D6F0000ABCDEF;
+        let port = Canned::new(&[b"004F0000000D6F0000ABCDEF05",
+                                 b"00560000000D6F0000ABCDEF0200",
+                                 b"00990000000D6F0000ABCDEF12"]);
+        let mut protocol = Protocol::new(port);
+        let events = protocol.subscribe();
+
+        protocol.poll().unwrap();
+
+        match events.try_recv() {
+            Ok(Event::Awake(awake_mac, AwakeReason::Button)) => assert_eq!(mac, awake_mac),
+            other => panic!("unexpected event: {:?}", other)
+        }
+        match events.try_recv() {
+            Ok(Event::SwitchGroup(switch_mac, 2, false)) => assert_eq!(mac, switch_mac),
+            other => panic!("unexpected event: {:?}", other)
+        }
+        match events.try_recv() {
+            Ok(Event::Unknown(0x0099, ref raw)) =>
+                assert_eq!(&b"00990000000D6F0000ABCDEF12"[..], &raw[..]),
+            other => panic!("unexpected event: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn canned_unknown_message() {
+        let mac = 0x0123456789abcdef;
+        let port = Canned::new(&[b"0000000100C1",
+                                 b"00990000000D6F0000ABCDEF12345678",
+                                 b"000E00010123456789ABCDEF4A480012"]);
+        let mut debug = vec![];
+        {
+            let mut protocol = Protocol::new(port);
+            protocol.set_snoop(ProtocolSnoop::Debug(&mut debug));
+
+            // the unknown message is neither mistaken for the response nor aborting the request
+            let _ = protocol.ping(mac).unwrap();
+        }
+
+        let debug = String::from_utf8(debug).unwrap();
+        assert!(debug.contains("< Unknown(msgid: 0099, count: 0000, mac: 000D6F0000ABCDEF, \
+                                payload: 12345678)"));
+    }
+
+    #[test]
+    fn stub_remove_node() {
+        let mac = 0x0123456789abcdef;
+        let port = stub::Stub::new();
+        let mut protocol = Protocol::new(port);
+
+        let circle_plus = protocol.initialize().unwrap().network_id;
+        let _ = protocol.calibrate(mac).unwrap();
+
+        let res = protocol.remove_node(circle_plus, mac).unwrap();
+        assert_eq!(mac, res.mac);
+        assert_eq!(true, res.removed);
+        assert_eq!(None, protocol.get_linked_node(circle_plus, 0).unwrap().mac);
+
+        let res = protocol.remove_node(circle_plus, mac).unwrap();
+        assert_eq!(false, res.removed);
+    }
+
+    #[test]
+    fn canned_switch_nack() {
+        let mac = 0x0123456789abcdef;
+        let port = Canned::new(&[b"0000000000C1",
+                                 b"0000000000E20123456789ABCDEF"]);
+        let mut protocol = Protocol::new(port);
+
+        match protocol.switch(mac, true) {
+            Err(error::PlError::Nack(AckStatus::RelaySwitchFailed, Some(nack_mac))) =>
+                assert_eq!(mac, nack_mac),
+            other => panic!("unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn canned_switch_timeout() {
+        let mac = 0x0123456789abcdef;
+        let port = Canned::new(&[b"0000000100C1",
+                                 b"0000000100E10123456789ABCDEF",
+                                 b"0000000200C1",
+                                 b"0000000200D80123456789ABCDEF"]);
+        let mut protocol = Protocol::new(port);
+
+        // the switch request is repeated when the Circle did not respond to the USB stick
+        protocol.switch(mac, true).unwrap();
+    }
+
+    #[test]
+    fn canned_switch_unexpected_state() {
+        let mac = 0x0123456789abcdef;
+        let port = Canned::new(&[b"0000000000C1",
+                                 b"0000000000DE0123456789ABCDEF"]);
+        let mut protocol = Protocol::new(port);
+
+        assert!(protocol.switch(mac, true).is_err());
+    }
+
+    #[test]
+    fn stub_switch_group() {
+        let macs = [0x0123456789abcdef, 0xfedcba9876543210, 0x0011223344556677];
+        let port = stub::Stub::new();
+        let mut protocol = Protocol::new(port);
+
+        let result = protocol.switch_group(&macs, true).unwrap();
+        assert_eq!(3, result.len());
+        assert!(result.values().all(|res| res.is_ok()));
+
+        for &mac in &macs {
+            assert_eq!(true, protocol.get_info(mac).unwrap().relay_state);
+        }
+    }
+
+    #[test]
+    fn canned_switch_group_partial() {
+        let mac1 = 0x0123456789abcdef;
+        let mac2 = 0xfedcba9876543210;
+        let mac3 = 0x0011223344556677;
+        let port = Canned::new(&[b"0000000100C1",
+                                 b"0000000200C1",
+                                 b"0000000300C1",
+                                 b"0000000300E2FEDCBA9876543210",
+                                 b"0000000200D80123456789ABCDEF"]);
+        let mut protocol = Protocol::new(port);
+
+        let result = protocol.switch_group(&[mac1, mac2, mac3], true).unwrap();
+        assert!(result[&mac1].is_ok());
+        match result[&mac2] {
+            Err(error::PlError::Nack(AckStatus::RelaySwitchFailed, _)) => {},
+            ref other => panic!("unexpected result: {:?}", other)
+        }
+        match result[&mac3] {
+            Err(error::PlError::Io(ref e)) => assert_eq!(io::ErrorKind::TimedOut, e.kind()),
+            ref other => panic!("unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn canned_stale_response() {
+        let mac = 0x0123456789abcdef;
+        let port = Canned::new(&[b"0000000500C1",
+                                 b"000E00040123456789ABCDEF10200012",
+                                 b"000E00050123456789ABCDEF4A480012"]);
+        let mut protocol = Protocol::new(port);
+
+        // a late response of an earlier request is not mistaken for the response of the request
+        let ping = protocol.ping(mac).unwrap();
+        assert_eq!(0x4A, ping.rssi_in);
+        assert_eq!(0x48, ping.rssi_out);
+    }
+
+    #[test]
+    fn canned_power_usage_group() {
+        let mac1 = 0x0123456789abcdef;
+        let mac2 = 0xfedcba9876543210;
+        let port = Canned::new(&[b"0000000100C1",
+                                 b"0000000200C1",
+                                 b"00130002FEDCBA98765432100001000A000001F4000000640000",
+                                 b"001300070123456789ABCDEF0001000A000001F4000000640000",
+                                 b"0000000100E1",
+                                 b"0000000300C1",
+                                 b"001300030123456789ABCDEF0001000A000001F4000000640000"]);
+        let mut protocol = Protocol::new(port);
+
+        // both requests are sent before any response is received, the request of the Circle which
+        // did not respond is repeated
+        let result = protocol.get_power_usage_group(&[mac1, mac2]).unwrap();
+        assert!(result[&mac1].is_ok());
+        assert!(result[&mac2].is_ok());
+    }
+
+    #[test]
+    fn canned_linked_node_group() {
+        let mac = 0x000D6F0000C0FFEE;
+        let port = Canned::new(&[b"0000000100C1",
+                                 b"0000000200C1",
+                                 b"00190001000D6F0000C0FFEE0123456789ABCDEF00",
+                                 b"0000000200E1"]);
+        let mut protocol = Protocol::new(port);
+        protocol.set_retries(0);
+
+        // a entry which cannot be retrieved is reported separately
+        let result = protocol.get_linked_node_group(mac, &[0, 1]).unwrap();
+        assert_eq!(Some(0x0123456789abcdef), result[&0].as_ref().unwrap().mac);
+        match result[&1] {
+            Err(error::PlError::Nack(AckStatus::Timeout, Some(nack_mac))) =>
+                assert_eq!(mac, nack_mac),
+            ref other => panic!("unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn stub_reconnect() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mac = 0x0123456789abcdef;
+        let reopened = Arc::new(AtomicUsize::new(0));
+        let mut protocol = Protocol::new(Unplugged{stub: stub::Stub::new(), requests: 2});
+        let reconnect = Reconnect {
+            attempts: 2,
+            backoff: Duration::from_millis(1)
+        };
+        let counter = reopened.clone();
+        protocol.set_reconnect(reconnect, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(Unplugged{stub: stub::Stub::new(), requests: 100})
+        });
+
+        let _ = protocol.initialize().unwrap();
+        let _ = protocol.calibrate(mac).unwrap();
+        // the USB stick is gone, the request succeeds after reconnecting
+        protocol.switch(mac, true).unwrap();
+        assert_eq!(1, reopened.load(Ordering::SeqCst));
+        assert!(protocol.get_calibration(mac).is_some());
+
+        // the link is restored regardless of the retries
+        let reopened = Arc::new(AtomicUsize::new(0));
+        let mut protocol = Protocol::new(Unplugged{stub: stub::Stub::new(), requests: 2});
+        protocol.set_retries(0);
+        let counter = reopened.clone();
+        protocol.set_reconnect(reconnect, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(Unplugged{stub: stub::Stub::new(), requests: 100})
+        });
+        let _ = protocol.initialize().unwrap();
+        let _ = protocol.calibrate(mac).unwrap();
+        protocol.switch(mac, true).unwrap();
+        assert_eq!(1, reopened.load(Ordering::SeqCst));
+
+        // a link which is lost again right after reconnecting is given up eventually
+        let mut protocol = Protocol::new(Unplugged{stub: stub::Stub::new(), requests: 2});
+        protocol.set_reconnect(reconnect, || Ok(Unplugged{stub: stub::Stub::new(), requests: 2}));
+        let _ = protocol.initialize().unwrap();
+        let _ = protocol.calibrate(mac).unwrap();
+        assert!(protocol.switch(mac, true).is_err());
+
+        // without reconnecting the error is reported
+        let mut protocol = Protocol::new(Unplugged{stub: stub::Stub::new(), requests: 0});
+        match protocol.switch(mac, true) {
+            Err(error::PlError::Io(ref e)) => assert_eq!(io::ErrorKind::BrokenPipe, e.kind()),
+            ref other => panic!("unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn stub_relay_lock() {
+        let mac = 0x0123456789abcdef;
+        let port = stub::Stub::new();
+        let mut protocol = Protocol::new(port);
+
+        assert_eq!(false, protocol.get_info(mac).unwrap().relay_locked);
+
+        protocol.switch(mac, true).unwrap();
+        protocol.set_relay_lock(mac, true).unwrap();
+        let info = protocol.get_info(mac).unwrap();
+        assert_eq!(true, info.relay_state);
+        assert_eq!(true, info.relay_locked);
+
+        protocol.set_relay_lock(mac, false).unwrap();
+        let info = protocol.get_info(mac).unwrap();
+        assert_eq!(true, info.relay_state);
+        assert_eq!(false, info.relay_locked);
+    }
+
+    #[test]
+    fn stub_schedule() {
+        let mac = 0x0123456789abcdef;
+        let port = stub::Stub::new();
+        let mut protocol = Protocol::new(port);
+
+        protocol.set_schedule(mac, 0, [true, true, false, false]).unwrap();
+        protocol.enable_schedule(mac, true).unwrap();
+        protocol.enable_schedule(mac, false).unwrap();
+    }
+
     #[test]
     fn stub_set_clock() {
         let mac = 0x0123456789abcdef;
@@ -394,6 +1686,23 @@ mod tests {
         protocol.set_clock(mac, ReqClockSet::new_from_tm(time::now())).unwrap();
     }
 
+    #[test]
+    fn stub_realtime_clock() {
+        let port = stub::Stub::new();
+        let mut protocol = Protocol::new(port);
+
+        let circle_plus = protocol.initialize().unwrap().network_id;
+        let tm = time::now_utc();
+
+        protocol.set_realtime_clock(circle_plus, RealTimeClock::new_from_tm(tm)).unwrap();
+        let clock = protocol.get_realtime_clock(circle_plus).unwrap().to_tm().unwrap();
+
+        assert_eq!(tm.tm_min, clock.tm_min);
+        assert_eq!(tm.tm_wday, clock.tm_wday);
+        assert_eq!(tm.tm_mday, clock.tm_mday);
+        assert_eq!(tm.tm_year, clock.tm_year);
+    }
+
     #[test]
     fn stub_calibrate() {
         let mac = 0x0123456789abcdef;
@@ -409,7 +1718,173 @@ mod tests {
         let port = stub::Stub::new();
         let mut protocol = Protocol::new(port);
 
-        let _ = protocol.get_power_buffer(mac, 0).unwrap();
+        let _ = protocol.get_power_buffer(mac, 0, 3600, 3600).unwrap();
+    }
+
+    #[test]
+    fn canned_bidirectional_power() {
+        let mac = 0x0123456789abcdef;
+        let calibration = ResCalibration {
+            gain_a: 1.0,
+            gain_b: 0.0,
+            off_total: 0.0,
+            off_noise: 0.0
+        };
+        let port = Canned::new(&[b"0000000000C1",
+                                 b"001300000123456789ABCDEFFF85000A000001F4000000640000",
+                                 b"0000000100C1",
+                                 b"004900010123456789ABCDEF0D094D1C0000007B0D094D1CFFFFFF85\
+                                   0D094D58000000760D094D58FFFFFF8A00044000"]);
+        let mut protocol = Protocol::new(port);
+
+        let usage = protocol.get_power_usage(mac).unwrap();
+        assert!(usage.pulse_1s.to_watts(calibration) < 0.0);
+        assert!(usage.pulse_8s.to_watts(calibration) > 0.0);
+        assert!(usage.pulse_hour.to_kwh(calibration) > 0.0);
+        assert!(usage.pulse_hour_produced.to_kwh(calibration) < 0.0);
+
+        let buffer = protocol.get_power_buffer(mac, 0, 3600, 900).unwrap();
+        assert!(buffer.pulses1.to_kwh(calibration) > 0.0);
+        assert!(buffer.pulses2.to_kwh(calibration) < 0.0);
+        assert!(buffer.pulses3.to_kwh(calibration) > 0.0);
+        assert!(buffer.pulses4.to_kwh(calibration) < 0.0);
+    }
+
+    #[test]
+    fn canned_sleeping_device() {
+        let mac = 0x000D6F0000ABCDEF;
+        let port = Canned::new(&[b"004F0000000D6F0000ABCDEF00",
+                                 b"00560000000D6F0000ABCDEF0101",
+                                 b"004F0000000D6F0000ABCDEF03",
+                                 b"0000000100C1",
+                                 b"0000000100B3000D6F0000ABCDEF",
+                                 b"01050000000D6F0000ABCDEF8000651E"]);
+        let mut protocol = Protocol::new(port);
+
+        protocol.queue_sense_interval(mac, 5);
+        assert_eq!(1, protocol.pending_commands(mac));
+        protocol.poll().unwrap();
+        assert_eq!(0, protocol.pending_commands(mac));
+
+        let messages = protocol.take_unsolicited_from(mac);
+        assert_eq!(4, messages.len());
+        assert!(protocol.take_unsolicited_from(mac).is_empty());
+
+        match messages[0] {
+            Message::ResAwake(_, res) => assert_eq!(AwakeReason::Maintenance, res.reason),
+            ref other => panic!("unexpected message: {:?}", other)
+        }
+        match messages[1] {
+            Message::ResSwitchGroup(_, res) => {
+                assert_eq!(1, res.group);
+                assert_eq!(true, res.on);
+            },
+            ref other => panic!("unexpected message: {:?}", other)
+        }
+        match messages[3] {
+            Message::ResSenseReport(_, res) => {
+                assert!((res.humidity - 56.5).abs() < 0.1);
+                assert!((res.temperature - 22.5).abs() < 0.1);
+            },
+            ref other => panic!("unexpected message: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn canned_sleeping_device_during_request() {
+        let mac = 0x000D6F0000ABCDEF;
+        let circle = 0x0123456789abcdef;
+        let port = Canned::new(&[b"0000000100C1",
+                                 b"004F0000000D6F0000ABCDEF00",
+                                 b"000E00010123456789ABCDEF4A480012",
+                                 b"0000000200C1",
+                                 b"0000000200B3000D6F0000ABCDEF"]);
+        let mut protocol = Protocol::new(port);
+
+        // the command is sent as soon as the request during which the device woke up is finished
+        protocol.queue_sense_interval(mac, 5);
+        let _ = protocol.ping(circle).unwrap();
+        assert_eq!(0, protocol.pending_commands(mac));
+    }
+
+    #[test]
+    fn canned_sleeping_device_refused() {
+        let mac = 0x000D6F0000ABCDEF;
+        let port = Canned::new(&[b"004F0000000D6F0000ABCDEF00",
+                                 b"0000000100C1",
+                                 b"0000000100B4000D6F0000ABCDEF"]);
+        let mut protocol = Protocol::new(port);
+        let events = protocol.subscribe();
+
+        // a refused command is reported to the subscribers instead of failing the polling
+        protocol.queue_sense_interval(mac, 5);
+        protocol.poll().unwrap();
+        assert_eq!(0, protocol.pending_commands(mac));
+
+        let _ = events.try_recv(); // awake announcement
+        match events.try_recv() {
+            Ok(Event::CommandFailed(failed_mac, Some(AckStatus::SenseIntervalFailed))) =>
+                assert_eq!(mac, failed_mac),
+            other => panic!("unexpected event: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn canned_relay_switched() {
+        let mac = 0x0123456789abcdef;
+        let port = Canned::new(&[b"0000000100C1",
+                                 b"0000FFFD00DE0123456789ABCDEF",
+                                 b"0000000100D80123456789ABCDEF"]);
+        let mut protocol = Protocol::new(port);
+        let events = protocol.subscribe();
+
+        // the relay switched with the button is neither mistaken for the acknowledge of the
+        // request nor aborting the request
+        protocol.switch(mac, true).unwrap();
+        match events.try_recv() {
+            Ok(Event::RelaySwitched(switch_mac, false)) => assert_eq!(mac, switch_mac),
+            other => panic!("unexpected event: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn canned_undecodable_message() {
+        let mac = 0x0123456789abcdef;
+        let port = Canned::new(&[b"0000000100C1",
+                                 b"004F0000000D6F0000ABCDEF",
+                                 b"000E00010123456789ABCDEF4A480012"]);
+        let mut protocol = Protocol::new(port);
+        let events = protocol.subscribe();
+
+        // a truncated awake message is reported and skipped without aborting the request
+        let _ = protocol.ping(mac).unwrap();
+        match events.try_recv() {
+            Ok(Event::Unknown(0x004F, _)) => {},
+            other => panic!("unexpected event: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn canned_response_timeout() {
+        let mac = 0x0123456789abcdef;
+        let port = Canned::new(&[b"0000000100C1",
+                                 b"0000000100E1",
+                                 b"0000000200C1",
+                                 b"000E00020123456789ABCDEF4A480012"]);
+        let mut protocol = Protocol::new(port);
+
+        // the request is repeated when the node did not respond to the USB stick
+        let ping = protocol.ping(mac).unwrap();
+        assert_eq!(0x4A, ping.rssi_in);
+    }
+
+    #[test]
+    fn stub_set_log_interval() {
+        let mac = 0x0123456789abcdef;
+        let port = stub::Stub::new();
+        let mut protocol = Protocol::new(port);
+
+        protocol.set_log_interval(mac, 15, 0).unwrap();
     }
 
     #[test]
@@ -1,7 +1,7 @@
 use std::io;
 use std::cmp;
 use std::str;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use crc16::*;
 
 // NOTE: keep this component free of dependencies to other modules within this
@@ -9,6 +9,9 @@ use crc16::*;
 
 const HEADER: [u8; 4] = [5, 5, 3, 3];
 const FOOTER: [u8; 2] = [13, 10];
+const STICK: u64 = 0x000D6F00005717C4;
+const CIRCLE_PLUS: u64 = 0x000D6F0000C0FFEE;
+const UNLINKED_CIRCLE: u64 = 0x000D6F0000BADA55;
 
 // Simulation state
 #[derive(Debug, Copy, Clone)]
@@ -27,6 +30,10 @@ pub struct Stub {
     responses: Vec<Vec<u8>>,
     output: Vec<u8>,
     plug: BTreeMap<u64, PlugState>,
+    locked: BTreeSet<u64>,
+    join_mode: bool,
+    realtime_clock: Vec<u8>,
+    seq: u16,
 }
 
 impl Stub {
@@ -36,6 +43,10 @@ impl Stub {
             responses: vec![],
             output: vec![],
             plug: BTreeMap::<u64, PlugState>::new(),
+            locked: BTreeSet::<u64>::new(),
+            join_mode: false,
+            realtime_clock: b"56341205161026".to_vec(),
+            seq: 0,
         }
     }
 
@@ -48,13 +59,28 @@ impl Stub {
 
     fn handle_incoming(&mut self, buf: &[u8]) -> io::Result<()> {
         let (command, payload) = buf.split_at(4);
-        let (mac, payload) = if command != b"000A" {
+        let is_stick_command = match command {
+            b"000A" | b"0007" | b"0008" => true,
+            _ => false
+        };
+        let (mac, payload) = if !is_stick_command {
             let (mac, payload) = payload.split_at(16);
             (Stub::from_hex_buffer(mac), payload)
         } else {
             (0, payload)
         };
         let macbuf = format!("{:016X}", mac).into_bytes();
+
+        // every request is accepted by the USB stick with a new sequence number, which is also
+        // used by the response of the request
+        self.seq = self.seq.wrapping_add(1);
+        let seq = format!("{:04X}", self.seq);
+        self.responses.push(format!("0000{}00C1", seq).into_bytes());
+        if !is_stick_command && command != b"0018" && command != b"001C" &&
+           mac != CIRCLE_PLUS && mac != STICK {
+            // every addressed Circle is considered to be linked to the Circle+
+            self.plug.entry(mac).or_insert(PlugState::Off);
+        }
         if command == b"0017" {
             // remember switch state
             let (switch, _) = payload.split_at(2);
@@ -68,10 +94,40 @@ impl Stub {
         }
 
         match command {
-            b"000A" => self.responses.push(b"00110000000000000000000001010000000000000000000000".to_vec()),
+            b"000A" => self.responses.push(format!("0011{}{:016X}0101{:016X}ABCD00",
+                                                   seq, STICK, CIRCLE_PLUS).into_bytes()),
+            b"0007" => {
+                let (accept, payload) = payload.split_at(2);
+                let (mac, _) = payload.split_at(16);
+                if Stub::from_hex_buffer(accept) != 0 {
+                    self.plug.insert(Stub::from_hex_buffer(mac), PlugState::Off);
+                    // the Circle reports it has joined the network
+                    let mut ack = vec![];
+                    ack.extend(b"0000".iter().cloned());
+                    ack.extend(seq.bytes());
+                    ack.extend(b"00D9".iter().cloned());
+                    ack.extend(mac.iter().cloned());
+                    self.responses.push(ack);
+                }
+            },
+            b"0008" => {
+                let (enable, _) = payload.split_at(2);
+                self.join_mode = Stub::from_hex_buffer(enable) != 0;
+                if self.join_mode && !self.plug.contains_key(&UNLINKED_CIRCLE) {
+                    // a unlinked Circle is immediately noticing the network
+                    self.responses.push(format!("00060000{:016X}", UNLINKED_CIRCLE).into_bytes());
+                }
+            },
             b"0016"|b"0017" => {
+                let status: &[u8] = match self.plug.get(&mac) {
+                    _ if command == b"0016" => b"00D7",
+                    Some(&PlugState::On) => b"00D8",
+                    _ => b"00DE"
+                };
                 let mut ack = vec![];
-                ack.extend(b"000000000000".iter().cloned());
+                ack.extend(b"0000".iter().cloned());
+                ack.extend(seq.bytes());
+                ack.extend(status.iter().cloned());
                 ack.extend(macbuf);
                 self.responses.push(ack);
             },
@@ -82,36 +138,135 @@ impl Stub {
                     Some(&PlugState::Off) => 0,
                     Some(&PlugState::On) => 1
                 };
+                let state = if self.locked.contains(&mac) {
+                    state | 2
+                } else {
+                    state
+                };
+                let (hw_ver, node_type) = if mac == STICK {
+                    ("653907008500", 0)
+                } else {
+                    ("653907014023", 2)
+                };
                 let mut ack = vec![];
-                ack.extend(b"00240000".iter().cloned());
+                ack.extend(b"0024".iter().cloned());
+                ack.extend(seq.bytes());
                 ack.extend(macbuf);
-                ack.extend(format!("0F0489B800048398{:02X}856539070140234E0844C202", state).into_bytes());
+                ack.extend(format!("0F0489B800048398{:02X}85{}4E0844C2{:02X}",
+                                   state, hw_ver, node_type).into_bytes());
                 self.responses.push(ack);
             },
             b"0026" => {
                 let mut ack = vec![];
-                ack.extend(b"00270000".iter().cloned());
+                ack.extend(b"0027".iter().cloned());
+                ack.extend(seq.bytes());
                 ack.extend(macbuf);
                 ack.extend(b"00000000000000000000000000000000".iter().cloned());
                 self.responses.push(ack);
             },
             b"0048" => {
                 let mut ack = vec![];
-                ack.extend(b"00490000".iter().cloned());
+                ack.extend(b"0049".iter().cloned());
+                ack.extend(seq.bytes());
                 ack.extend(macbuf);
                 ack.extend(b"0D094D1C0000007B0D094D58000000760D094D94000000710D094DD00000003100044000".iter().cloned());
                 self.responses.push(ack);
             },
             b"0012" => {
                 let mut ack = vec![];
-                ack.extend(b"00130000".iter().cloned());
+                ack.extend(b"0013".iter().cloned());
+                ack.extend(seq.bytes());
                 ack.extend(macbuf);
                 ack.extend(b"0000000000000000000000000000".iter().cloned());
                 self.responses.push(ack);
             },
+            b"000D" => {
+                let mut ack = vec![];
+                ack.extend(b"000E".iter().cloned());
+                ack.extend(seq.bytes());
+                ack.extend(macbuf);
+                ack.extend(b"4A480012".iter().cloned());
+                self.responses.push(ack);
+            },
+            b"0018" => {
+                let (index, _) = payload.split_at(2);
+                let index = Stub::from_hex_buffer(index);
+                // the Circle+ is not part of its own node table
+                let mut nodes = self.plug.keys().filter(|&&mac| mac != CIRCLE_PLUS);
+                let node = match nodes.nth(index as usize) {
+                    None => 0xffffffffffffffff,
+                    Some(node) => *node
+                };
+                let mut ack = vec![];
+                ack.extend(b"0019".iter().cloned());
+                ack.extend(seq.bytes());
+                ack.extend(macbuf);
+                ack.extend(format!("{:016X}{:02X}", node, index).into_bytes());
+                self.responses.push(ack);
+            },
+            b"001C" => {
+                let (node, _) = payload.split_at(16);
+                let node = Stub::from_hex_buffer(node);
+                let removed = self.plug.remove(&node).is_some();
+                let mut ack = vec![];
+                ack.extend(b"001D".iter().cloned());
+                ack.extend(seq.bytes());
+                ack.extend(macbuf);
+                ack.extend(format!("{:016X}{:02X}", node, removed as u8).into_bytes());
+                self.responses.push(ack);
+            },
+            b"0028" => {
+                let (clock, _) = payload.split_at(14);
+                self.realtime_clock = clock.to_vec();
+                let mut ack = vec![];
+                ack.extend(b"0000".iter().cloned());
+                ack.extend(seq.bytes());
+                ack.extend(b"00DF".iter().cloned());
+                ack.extend(macbuf);
+                self.responses.push(ack);
+            },
+            b"0029" => {
+                let mut ack = vec![];
+                ack.extend(b"003A".iter().cloned());
+                ack.extend(seq.bytes());
+                ack.extend(macbuf);
+                ack.extend(self.realtime_clock.iter().cloned());
+                self.responses.push(ack);
+            },
+            b"0040" | b"0058" => {
+                let mut ack = vec![];
+                ack.extend(b"0000".iter().cloned());
+                ack.extend(seq.bytes());
+                ack.extend(b"00F9".iter().cloned());
+                ack.extend(macbuf);
+                self.responses.push(ack);
+            },
+            b"004E" => {
+                let (lock, _) = payload.split_at(2);
+                if Stub::from_hex_buffer(lock) != 0 {
+                    self.locked.insert(mac);
+                } else {
+                    self.locked.remove(&mac);
+                }
+                let mut ack = vec![];
+                ack.extend(b"0000".iter().cloned());
+                ack.extend(seq.bytes());
+                ack.extend(b"00F1".iter().cloned());
+                ack.extend(macbuf);
+                self.responses.push(ack);
+            },
+            b"0057" => {
+                let mut ack = vec![];
+                ack.extend(b"0000".iter().cloned());
+                ack.extend(seq.bytes());
+                ack.extend(b"00F8".iter().cloned());
+                ack.extend(macbuf);
+                self.responses.push(ack);
+            },
             b"003E" => {
                 let mut ack = vec![];
-                ack.extend(b"003F0000".iter().cloned());
+                ack.extend(b"003F".iter().cloned());
+                ack.extend(seq.bytes());
                 ack.extend(macbuf);
                 ack.extend(b"0B243A0601457A".iter().cloned());
                 self.responses.push(ack);
@@ -127,7 +282,8 @@ impl io::Read for Stub {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if self.output.is_empty() {
             if self.responses.is_empty() {
-                return Err(io::Error::new(io::ErrorKind::Other, "no response pending"));
+                // behave like a serial port of which the read timeout expired
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "no response pending"));
             }
 
             let new_response = self.responses.remove(0);
@@ -151,8 +307,9 @@ impl io::Read for Stub {
 impl io::Write for Stub {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.input.extend(buf.iter().cloned());
-        // test whether a complete message has been received
-        if let Some(pos) = self.input.iter().position(|x| *x==b'\r') {
+        // handle every complete message which has been received (several
+        // messages might be written at once)
+        while let Some(pos) = self.input.iter().position(|x| *x==b'\r') {
             let input = self.input.clone();
             let (buf, _) = input.split_at(pos);
 
@@ -161,13 +318,13 @@ impl io::Write for Stub {
                 let (_, buf) = buf.split_at(rpos + 1);
 
                 try!(self.handle_incoming(buf));
+            }
 
-                // no other way to deque a specific amount of data (or would
-                // `self.input.drain().take(pos + 1)` a better approach in the
-                // future?
-                for _ in 0..pos + 1 {
-                    let _ = self.input.remove(0);
-                }
+            // no other way to deque a specific amount of data (or would
+            // `self.input.drain().take(pos + 1)` a better approach in the
+            // future?
+            for _ in 0..pos + 1 {
+                let _ = self.input.remove(0);
             }
         }
         Ok((buf.len()))
@@ -0,0 +1,67 @@
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Connection to a Plugwise USB stick which is exposed by a remote serial server (i.e. ser2net in
+/// "raw" mode). Both connecting and the communication itself are bounded by the timeout of the
+/// port. A lost connection is not made again by the port itself; the link is restored by opening a
+/// new port (see `Reconnect`).
+pub struct TcpPort {
+    stream: TcpStream
+}
+
+impl TcpPort {
+    pub fn connect(addr: String, timeout: Duration) -> io::Result<TcpPort> {
+        info!("connecting to {}", addr);
+        let stream = try!(TcpPort::connect_helper(&addr, timeout));
+        try!(stream.set_read_timeout(Some(timeout)));
+        try!(stream.set_write_timeout(Some(timeout)));
+        try!(stream.set_nodelay(true));
+
+        Ok(TcpPort {
+            stream: stream
+        })
+    }
+
+    // connect to the first address (of the resolved addresses) which accepts the connection
+    // within the timeout
+    fn connect_helper(addr: &str, timeout: Duration) -> io::Result<TcpStream> {
+        let mut result = Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                            "address could not be resolved"));
+
+        for addr in try!(addr.to_socket_addrs()) {
+            result = TcpStream::connect_timeout(&addr, timeout);
+            if result.is_ok() {
+                break;
+            }
+        }
+
+        result
+    }
+}
+
+impl io::Read for TcpPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf) {
+            Ok(0) if !buf.is_empty() => {
+                Err(io::Error::new(io::ErrorKind::ConnectionAborted, "connection closed by peer"))
+            },
+            // behave like a serial port of which the read timeout expired
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock ||
+                          e.kind() == io::ErrorKind::TimedOut => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, "no data received"))
+            },
+            result => result
+        }
+    }
+}
+
+impl io::Write for TcpPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}